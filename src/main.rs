@@ -1,21 +1,56 @@
 use chrono::Utc;
-use clap::Parser;
-use futures::stream::{self, StreamExt};
+use clap::{ArgGroup, Parser, Subcommand, ValueEnum};
+use futures::stream::{self, Stream, StreamExt};
+use ipnet::IpNet;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use rand::SeedableRng;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
-use std::io::{self, BufRead};
-use std::path::PathBuf;
-use std::time::Duration;
-use trust_dns_resolver::config::{ResolverConfig, ResolverOpts};
+use std::io::{self, BufRead, IsTerminal};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use tdigest::TDigest;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::sync::{mpsc, Semaphore, SemaphorePermit};
+use tokio::task::JoinHandle;
+use trust_dns_proto::error::ProtoErrorKind;
+use trust_dns_proto::rr::dnssec::rdata::tsig::TsigAlgorithm;
+use trust_dns_proto::rr::dnssec::tsig::TSigner;
+use trust_dns_proto::rr::rdata::caa::Value as CaaValue;
+use trust_dns_proto::rr::rdata::opt::{ClientSubnet, EdnsOption};
+use trust_dns_proto::rr::rdata::CAA;
+use trust_dns_proto::rr::{LowerName, RecordSet, RrKey};
+use trust_dns_proto::serialize::binary::{BinDecodable, BinDecoder};
+use trust_dns_proto::serialize::txt::{Lexer, Parser as ZoneParser};
+use trust_dns_resolver::config::{
+    NameServerConfig, NameServerConfigGroup, Protocol, ResolverConfig, ResolverOpts,
+};
+use trust_dns_resolver::error::{ResolveError, ResolveErrorKind};
+use trust_dns_resolver::lookup::Lookup;
+use trust_dns_resolver::proto::op::{Edns, Message, MessageType, OpCode, Query, ResponseCode};
+use trust_dns_resolver::proto::xfer::DnsResponse;
+use trust_dns_resolver::proto::rr::{DNSClass, Name, RData, RecordType};
 use trust_dns_resolver::TokioAsyncResolver;
 
 #[derive(Parser, Debug)]
 #[command(
     name = "domain-checker",
     about = "Check if domain names are registered using DNS lookups",
-    version
+    version,
+    group = ArgGroup::new("randomized").args(["shuffle", "sample"]).multiple(true)
 )]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Domain names to check (optional if reading from stdin)
     #[arg(required = false)]
     domains: Vec<String>,
@@ -24,280 +59,5641 @@ struct Cli {
     #[arg(short, long, default_value = "10")]
     concurrent: usize,
 
+    /// Halve concurrency when errors spike and ramp back up while checks succeed
+    #[arg(long)]
+    adaptive: bool,
+
+    /// Lowest concurrency --adaptive may back off to
+    #[arg(long, value_name = "N", default_value = "1", requires = "adaptive")]
+    min_concurrent: usize,
+
+    /// Space out new checks while the resolver's rolling average response time is above
+    /// --target-latency-ms, and close the gap again once it recovers
+    #[arg(long)]
+    latency_adaptive: bool,
+
+    /// Rolling average response time --latency-adaptive tries to stay under
+    #[arg(long, value_name = "MS", default_value = "250", requires = "latency_adaptive")]
+    target_latency_ms: u64,
+
     /// Output as JSON to stdout
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     json: bool,
 
-    /// Save output to JSON file
+    /// Emit JSON domains as an object keyed by domain name instead of an array
+    #[arg(long)]
+    json_map: bool,
+
+    /// Emit JSON domains nested by TLD, then keyed by domain name, for treemap-style visualizations
+    #[arg(long, conflicts_with = "json_map")]
+    json_by_tld: bool,
+
+    /// Stream NDJSON events (started, result, progress, finished) to stdout instead of a final report
+    #[arg(long, conflicts_with = "json")]
+    events: bool,
+
+    /// Show a live dashboard while checking (ignored when stdout isn't a terminal)
+    #[arg(long, conflicts_with = "events")]
+    tui: bool,
+
+    /// Print one logfmt line (key=value pairs) per domain instead of the text report
+    #[arg(long, conflicts_with_all = ["json", "events"])]
+    logfmt: bool,
+
+    /// Format printed to stdout, independent of --output-file's format (--json and --logfmt are
+    /// shorthands for json and logfmt)
+    #[arg(long, value_enum, value_name = "FORMAT", conflicts_with_all = ["json", "logfmt", "events"])]
+    format: Option<OutputFormat>,
+
+    /// Byte-stable output for committing snapshots: sort domains, records and keys, drop timings and timestamps
+    #[arg(long)]
+    stable: bool,
+
+    /// Syntax-highlight JSON printed to stdout
+    #[arg(long, value_enum, value_name = "WHEN", default_value = "auto")]
+    color_json: ColorWhen,
+
+    /// Save output to a file, as JSON unless --output-format or a .logfmt extension says otherwise
     #[arg(long)]
     output_file: Option<PathBuf>,
 
+    /// Format of --output-file [default: logfmt for a .logfmt extension, anything else is json]
+    #[arg(long, value_enum, value_name = "FORMAT", requires = "output_file")]
+    output_format: Option<OutputFormat>,
+
+    /// Also save the result as MessagePack, for shipping to a collector (see `read-msgpack`)
+    #[arg(long, value_name = "PATH")]
+    msgpack: Option<PathBuf>,
+
+    /// Also write per-domain gauges (domain_registered, domain_response_time_ms) labeled by domain
+    /// in OpenMetrics text format, for scraping via a textfile collector
+    #[arg(long, value_name = "PATH")]
+    openmetrics_file: Option<PathBuf>,
+
+    /// Most domains --openmetrics-file writes series for; each domain is a separate series, so
+    /// large lists can overwhelm a TSDB
+    #[arg(long, value_name = "N", default_value = "1000", requires = "openmetrics_file")]
+    openmetrics_max_domains: usize,
+
+    /// Also write the result split by verdict into registered.json, available.json and
+    /// errors.json in DIR; buckets with no domains get no file
+    #[arg(long, value_name = "DIR")]
+    split_by_status: Option<PathBuf>,
+
+    /// Rewrite the output file with partial results every N completed domains
+    #[arg(long, value_name = "N", requires = "output_file")]
+    flush_every: Option<usize>,
+
     /// Strip whitespace and empty lines from input
     #[arg(long)]
     clean: bool,
 
+    /// Drop repeated domains as stdin is read using a scalable Bloom filter, so a repeat is never
+    /// stored, at the cost of very occasionally dropping a domain seen for the first time
+    #[arg(long, conflicts_with_all = ["domains", "jsonl", "input_files", "import_url", "brand_csv", "replay"])]
+    dedup_approx: bool,
+
+    /// Read stdin as JSON lines: {"domain": "...", "expected_ips": ["..."]}
+    #[arg(long)]
+    jsonl: bool,
+
+    /// Read domains from a file instead of stdin (repeatable); each file gets its own summary
+    #[arg(long = "input-file", value_name = "PATH", conflicts_with_all = ["jsonl", "domains"])]
+    input_files: Vec<PathBuf>,
+
+    /// Fetch the domains to check from an HTTP endpoint returning a JSON array of names or an
+    /// object with a `domains` array, such as a registrar's portfolio API
+    #[arg(long, value_name = "URL", conflicts_with_all = ["input_files", "jsonl", "domains", "brand_csv"])]
+    import_url: Option<String>,
+
+    /// Bearer token sent with the --import-url request
+    #[arg(long, value_name = "TOKEN", requires = "import_url")]
+    import_token: Option<String>,
+
+    /// Check the localized names from a `brand,locale,localized_name` CSV under every --tlds TLD
+    #[arg(long, value_name = "PATH", requires = "tlds", conflicts_with_all = ["input_files", "jsonl", "domains"])]
+    brand_csv: Option<PathBuf>,
+
+    /// TLDs to check each --brand-csv name under (comma-separated)
+    #[arg(long, value_name = "TLD,...", value_delimiter = ',', requires = "brand_csv")]
+    tlds: Vec<String>,
+
+    /// Exit with status 1 if any domain doesn't resolve to its expected IPs
+    #[arg(long)]
+    assert_ips: bool,
+
+    /// File of CIDRs or IPs (one per line, # comments); flags domains that resolve into any of them
+    #[arg(long, value_name = "PATH")]
+    ip_blocklist: Option<PathBuf>,
+
+    /// Exit with status 1 if any domain resolves to a blocklisted IP
+    #[arg(long, requires = "ip_blocklist")]
+    fail_on_blocklisted: bool,
+
+    /// CA domain (e.g. letsencrypt.org) that CAA records may authorize (repeatable); sets
+    /// caa_compliant to false when a domain has no CAA or authorizes any other CA
+    #[arg(long = "require-caa-issuer", value_name = "CA")]
+    caa_issuers: Vec<String>,
+
+    /// Exit with status 1 if any domain is not CAA compliant
+    #[arg(long, requires = "caa_issuers")]
+    fail_on_caa: bool,
+
+    /// Exit with CODE when any domain meets CONDITION (repeatable; first match wins, before the
+    /// --assert, --assert-ips, --fail-on-caa and --fail-on-blocklisted checks). Conditions:
+    /// registered, unregistered, errors, unknown, ip-mismatch, caa-noncompliant, blocklisted,
+    /// assertion-failed
+    #[arg(long, value_name = "CONDITION=CODE", value_parser = parse_exit_code_on)]
+    exit_code_on: Vec<(ExitCondition, i32)>,
+
     /// Show only unregistered domains in output
     #[arg(short = 'u', long)]
     unregistered_only: bool,
+
+    /// Show only domains whose verdict needs a second look: SERVFAILs, timeouts and other lookup
+    /// errors, unknown verdicts, resolver or source disagreement, and suspected wildcards (--decision)
+    #[arg(long)]
+    uncertain_only: bool,
+
+    /// Show only domains that are available for the first time, remembering those already shown
+    /// in STATE_FILE (one domain per line) so each is reported once across runs
+    #[arg(long, value_name = "STATE_FILE")]
+    notify_once: Option<PathBuf>,
+
+    /// Check every domain against RULE and report which rules passed, exiting with status 1 if any
+    /// failed (repeatable). Rules: FIELD (has a value), FIELD=VALUE, FIELD~REGEX; fields as for --match
+    #[arg(long = "assert", value_name = "RULE", value_parser = parse_assertion)]
+    assertions: Vec<Assertion>,
+
+    /// Show only domains where FIELD has a value matching REGEX (repeatable, all must match)
+    #[arg(long = "match", value_name = "FIELD=REGEX", value_parser = parse_record_match)]
+    matches: Vec<RecordMatch>,
+
+    /// Include latency statistics (min/mean/max and p50/p90/p99) in the output
+    #[arg(long)]
+    stats: bool,
+
+    /// Print a sparkline of the response time distribution to stderr when the run finishes
+    #[arg(long)]
+    latency_sparkline: bool,
+
+    /// Report nameservers, IPs and MX hosts shared by more than one registered domain
+    #[arg(long)]
+    infra_report: bool,
+
+    /// Sweep NS records first and run the full check only on domains that didn't get a definite
+    /// NXDOMAIN; every domain still gets the full check under --decision, --consensus, --rdap or
+    /// --source-precedence
+    #[arg(long)]
+    two_phase: bool,
+
+    /// Cap concurrent checks for one TLD, overriding the builtin caps; 0 removes a cap (repeatable)
+    #[arg(long, value_name = "TLD=N", value_parser = parse_tld_concurrency)]
+    tld_concurrency: Vec<(String, usize)>,
+
+    /// Print the effective per-TLD concurrency caps and exit
+    #[arg(long)]
+    show_tld_concurrency: bool,
+
+    /// Check the domains in PATH first, in a lane of their own with --priority-concurrent checks
+    /// in flight, before the rest of the list; results are merged into one report
+    #[arg(long, value_name = "PATH")]
+    priority_file: Option<PathBuf>,
+
+    /// Maximum number of concurrent checks in the --priority-file lane
+    #[arg(long, value_name = "N", default_value = "50", requires = "priority_file", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    priority_concurrent: usize,
+
+    /// Check domains in random order
+    #[arg(long)]
+    shuffle: bool,
+
+    /// Check only N domains drawn at random from the input and estimate the registration rate of
+    /// the whole input, with a 95% confidence interval
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    sample: Option<u64>,
+
+    /// Seed for --shuffle and --sample, to reproduce a previous run's order (recorded in the output)
+    #[arg(long, requires = "randomized")]
+    seed: Option<u64>,
+
+    /// Answer lookups from a BIND-style zone file instead of live DNS
+    #[arg(long, value_name = "PATH")]
+    zone_file: Option<PathBuf>,
+
+    /// Origin for a --zone-file without an $ORIGIN directive
+    #[arg(long, value_name = "NAME", requires = "zone_file")]
+    zone_origin: Option<String>,
+
+    /// Answer every check from a saved result (JSON or --msgpack) instead of DNS; with no domains
+    /// given, replays all recorded domains
+    #[arg(long, value_name = "FILE", conflicts_with = "zone_file")]
+    replay: Option<PathBuf>,
+
+    /// Resolver preset to query when no --nameserver is given
+    #[arg(long, value_enum, value_name = "PRESET", default_value = "cloudflare", global = true)]
+    resolver: ResolverPreset,
+
+    /// Nameserver to query instead of the --resolver preset (repeatable)
+    #[arg(long = "nameserver", value_name = "IP[:PORT]", value_parser = parse_nameserver, global = true)]
+    nameservers: Vec<SocketAddr>,
+
+    /// Sign NS and address queries with this TSIG key, as dig -y takes it: a base64 secret and
+    /// hmac-sha256 unless hmac-sha384 or hmac-sha512 is given. The queries go straight to the first
+    /// --nameserver, which is required, for internal servers that only answer authenticated queries
+    #[arg(long, value_name = "[ALGORITHM:]NAME:SECRET", value_parser = parse_tsig_key, requires = "nameservers")]
+    tsig_key: Option<TsigKey>,
+
+    /// Drop nameservers that fail a startup probe instead of aborting
+    #[arg(long)]
+    skip_unreachable_resolvers: bool,
+
+    /// Seconds to wait for each DNS query
+    #[arg(long, value_name = "SECONDS", default_value = "2", value_parser = parse_seconds)]
+    timeout: Duration,
+
+    /// Seconds to wait when connecting (TCP, HTTP) or probing --nameserver at startup, so a down
+    /// server fails fast while a slow one still gets --timeout to answer [default: --timeout]
+    #[arg(long, value_name = "SECONDS", value_parser = parse_seconds)]
+    connect_timeout: Option<Duration>,
+
+    /// Give up on a domain after MS milliseconds of wall-clock time, however many queries, retries
+    /// and fallbacks are still pending, and report it as error_kind "domain_deadline"
+    #[arg(long, value_name = "MS", value_parser = clap::value_parser!(u64).range(1..))]
+    domain_deadline: Option<u64>,
+
+    /// Resolver preset or IP[:PORT] to retry with when the primary answer is SERVFAIL or a timeout
+    #[arg(long, value_name = "CONFIG", value_parser = parse_fallback_resolver)]
+    fallback_resolver: Option<FallbackResolver>,
+
+    /// Re-ask a second resolver about every NXDOMAIN and report the domain unregistered only if it
+    /// agrees; otherwise flag it uncertain and count it as unknown
+    #[arg(long, conflicts_with_all = ["zone_file", "replay"])]
+    confirm_nxdomain: bool,
+
+    /// Resolver preset or IP[:PORT] for --confirm-nxdomain [default: google, or cloudflare when
+    /// the primary resolver is google]
+    #[arg(long, value_name = "CONFIG", value_parser = parse_fallback_resolver, requires = "confirm_nxdomain")]
+    confirm_resolver: Option<FallbackResolver>,
+
+    /// Also ask every configured nameserver separately and report the majority verdict; flag the
+    /// domain uncertain when fewer than N nameservers agree on it
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..), conflicts_with_all = ["zone_file", "replay"])]
+    consensus: Option<u32>,
+
+    /// Pause when every recent lookup fails, wait for the resolver to answer again, then re-check
+    /// the failed domains instead of running the rest of the list into errors
+    #[arg(long, conflicts_with_all = ["zone_file", "replay"])]
+    auto_recover: bool,
+
+    /// Longest pause --auto-recover waits for the resolver before carrying on regardless
+    #[arg(long, value_name = "SECONDS", default_value = "300", value_parser = parse_seconds, requires = "auto_recover")]
+    recover_max_wait: Duration,
+
+    /// Troubleshooting: use a new resolver per query so no socket or connection is reused (slower)
+    #[arg(long)]
+    fresh_ports: bool,
+
+    /// Drop syntactically invalid domains before checking
+    #[arg(long)]
+    validate: bool,
+
+    /// Report invalid domains as flagged results instead of dropping them (implies --validate)
+    #[arg(long)]
+    keep_invalid: bool,
+
+    /// Which address records to query
+    #[arg(long, value_enum, default_value = "both")]
+    ip_version: IpVersion,
+
+    /// Look up TXT records (enabled automatically by --match or --assert on txt_records)
+    #[arg(long)]
+    txt: bool,
+
+    /// Treat every domain as ENUM and look up NAPTR records (automatic for *.e164.arpa)
+    #[arg(long = "enum")]
+    enum_all: bool,
+
+    /// Report the SPF record (and the records its include: terms point to) and the DMARC policy
+    #[arg(long)]
+    email_policy: bool,
+
+    /// Look up DNAME records, which redirect a domain's whole subtree elsewhere
+    #[arg(long)]
+    dname: bool,
+
+    /// Resolve the IP addresses of every nameserver found
+    #[arg(long)]
+    resolve_all_ns: bool,
+
+    /// Send an SOA query straight to each nameserver and report which ones answer
+    #[arg(long)]
+    check_ns_reachable: bool,
+
+    /// Ask each nameserver directly for the domain's A and NS records and report whether they all
+    /// serve the same ones (ns_consistent), listing those that differ from the majority
+    #[arg(long, conflicts_with_all = ["zone_file", "replay"])]
+    check_ns_consistency: bool,
+
+    /// Resolve registered domains' addresses as a client in this region would see them
+    /// (repeatable), reporting each answer set and geo_variance when they differ. Public resolvers
+    /// are anycast, so each region is a client subnet sent to --vantage-resolver rather than a
+    /// resolver of its own
+    #[arg(long = "vantage", value_enum, value_name = "REGION", conflicts_with_all = ["zone_file", "replay"])]
+    vantages: Vec<Vantage>,
+
+    /// Resolver for --vantage queries; it must pass EDNS Client Subnet on to authoritative servers
+    #[arg(long, value_name = "IP[:PORT]", default_value = "8.8.8.8", value_parser = parse_nameserver)]
+    vantage_resolver: SocketAddr,
+
+    /// Query NXDOMAIN answers a second time after --recheck-delay-ms before reporting unregistered
+    #[arg(long)]
+    recheck_nxdomain: bool,
+
+    /// Delay before the --recheck-nxdomain query
+    #[arg(long, default_value = "3000", value_name = "MS")]
+    recheck_delay_ms: u64,
+
+    /// Which records must be present for a domain to count as registered
+    #[arg(long, value_enum, value_name = "POLICY", default_value = "either")]
+    registered_requires: RegisteredRequires,
+
+    /// Add a `decision` object with the raw signals behind each verdict (NS, A and AAAA presence,
+    /// NXDOMAIN, and whether the parent zone's wildcard gives the same answer)
+    #[arg(long)]
+    decision: bool,
+
+    /// Report domains delegated to known parking nameservers as unregistered
+    #[arg(long)]
+    treat_parked_as_available: bool,
+
+    /// Count timed-out lookups as unknown in the summary instead of as errors
+    #[arg(long)]
+    timeout_as_unknown: bool,
+
+    /// Check HTTP to HTTPS redirects and TLS certificate expiry of registered domains
+    #[arg(long)]
+    tls_check: bool,
+
+    /// Look up registrar and registration expiry over RDAP and estimate when expired domains drop (drop_eta)
+    #[arg(long)]
+    rdap: bool,
+
+    /// Sources that decide the verdict when they disagree, first listed wins (comma-separated:
+    /// dns, rdap); the choice is recorded as verdict_source and the disagreement as a warning.
+    /// Listing rdap looks up every domain over RDAP, not just registered ones
+    #[arg(long, value_enum, value_name = "LIST", value_delimiter = ',')]
+    source_precedence: Vec<VerdictSource>,
+
+    /// Group domains by their RDAP registrar, most domains first
+    #[arg(long, requires = "rdap")]
+    registrar_report: bool,
+
+    /// Record the answer, authority and additional sections of the raw NS response
+    #[arg(long)]
+    full_response: bool,
+
+    /// Clear the RD bit so servers answer from their own data only, and report the AA bit (authoritative)
+    #[arg(long, global = true)]
+    no_recursion_desired: bool,
+
+    /// Serve the --events stream as Server-Sent Events to HTTP clients connecting to ADDR; clients
+    /// that connect late first get the started event, the last 1000 results and the latest progress
+    #[arg(long, value_name = "ADDR")]
+    serve: Option<SocketAddr>,
+
+    /// Hold the scan until the first --serve client connects instead of starting immediately
+    #[arg(long, requires = "serve")]
+    serve_wait: bool,
+
+    /// POST completed results as JSON arrays to this URL
+    #[arg(long, value_name = "URL")]
+    webhook: Option<String>,
+
+    /// Maximum number of results per webhook request
+    #[arg(long, default_value = "50")]
+    webhook_batch_size: usize,
+
+    /// Send a partial webhook batch after this many milliseconds
+    #[arg(long, default_value = "1000", value_parser = clap::value_parser!(u64).range(1..))]
+    webhook_flush_ms: u64,
+
+    /// Results that may queue for a slow sink (e.g. --webhook) before checking pauses
+    #[arg(long, value_name = "N", default_value = "256")]
+    output_buffer: usize,
+}
+
+/// Fields that `--match` can be applied to.
+const MATCH_FIELDS: &[&str] = &[
+    "domain",
+    "nameservers",
+    "ip_addresses",
+    "txt_records",
+    "mx_records",
+    "cnames",
+    "nameserver_ips",
+    "error",
+    "error_kind",
+    "cert_expiry",
+    "http_error",
+];
+
+#[derive(Debug, Clone)]
+struct RecordMatch {
+    field: String,
+    regex: Regex,
+}
+
+/// The values of one of the `MATCH_FIELDS` of a status.
+fn field_values(status: &DomainStatus, field: &str) -> Vec<String> {
+    let single = |value: &Option<String>| value.iter().cloned().collect::<Vec<_>>();
+    match field {
+        "domain" => vec![status.domain.clone()],
+        "nameservers" => status.nameservers.clone(),
+        "ip_addresses" => status.ip_addresses.clone(),
+        "txt_records" => status.txt_records.clone(),
+        "mx_records" => status.mx_records.clone(),
+        "cnames" => status.cnames.clone(),
+        "nameserver_ips" => status.nameserver_ips.iter().flat_map(|entry| entry.ips.clone()).collect(),
+        "error" => single(&status.error),
+        "error_kind" => single(&status.error_kind),
+        "cert_expiry" => single(&status.cert_expiry),
+        "http_error" => single(&status.http_error),
+        _ => Vec::new(),
+    }
+}
+
+fn check_field(field: &str) -> Result<(), String> {
+    if MATCH_FIELDS.contains(&field) {
+        Ok(())
+    } else {
+        Err(format!("unknown field '{}', expected one of: {}", field, MATCH_FIELDS.join(", ")))
+    }
+}
+
+impl RecordMatch {
+    fn matches(&self, status: &DomainStatus) -> bool {
+        field_values(status, &self.field).iter().any(|value| self.regex.is_match(value))
+    }
+}
+
+fn parse_record_match(value: &str) -> Result<RecordMatch, String> {
+    let (field, pattern) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected FIELD=REGEX, got '{}'", value))?;
+    check_field(field)?;
+    let regex = Regex::new(pattern).map_err(|e| e.to_string())?;
+    Ok(RecordMatch {
+        field: field.to_string(),
+        regex,
+    })
+}
+
+/// An `--assert` rule: FIELD has a value, FIELD=VALUE has that exact value (trailing dots
+/// ignored), FIELD~REGEX has a value matching REGEX.
+#[derive(Debug, Clone)]
+struct Assertion {
+    rule: String,
+    field: String,
+    check: AssertCheck,
+}
+
+#[derive(Debug, Clone)]
+enum AssertCheck {
+    Present,
+    Equals(String),
+    Matches(Regex),
+}
+
+/// Outcome of one `--assert` rule for one domain.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct AssertionResult {
+    rule: String,
+    passed: bool,
+}
+
+impl Assertion {
+    fn evaluate(&self, status: &DomainStatus) -> AssertionResult {
+        let values = field_values(status, &self.field);
+        let passed = match &self.check {
+            AssertCheck::Present => !values.is_empty(),
+            AssertCheck::Equals(expected) => values
+                .iter()
+                .any(|value| value.trim_end_matches('.').eq_ignore_ascii_case(expected.trim_end_matches('.'))),
+            AssertCheck::Matches(regex) => values.iter().any(|value| regex.is_match(value)),
+        };
+        AssertionResult { rule: self.rule.clone(), passed }
+    }
+}
+
+fn parse_assertion(value: &str) -> Result<Assertion, String> {
+    let (field, check) = match value.find(['=', '~']) {
+        Some(at) if value[at..].starts_with('=') => (&value[..at], AssertCheck::Equals(value[at + 1..].to_string())),
+        Some(at) => (&value[..at], AssertCheck::Matches(Regex::new(&value[at + 1..]).map_err(|e| e.to_string())?)),
+        None => (value, AssertCheck::Present),
+    };
+    check_field(field)?;
+    Ok(Assertion {
+        rule: value.to_string(),
+        field: field.to_string(),
+        check,
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// The full report: summary, then every domain's details
+    Text,
+    /// One line per domain with its verdict, then the summary
+    Compact,
+    Json,
+    /// One key=value line per domain
+    Logfmt,
+}
+
+impl OutputFormat {
+    /// The --output-file format its extension implies. Only `.logfmt` names a format outright;
+    /// `.txt`, `.log` and the rest stay JSON, as every output file was before --output-format.
+    fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()).map(str::to_ascii_lowercase).as_deref() {
+            Some("logfmt") => Self::Logfmt,
+            _ => Self::Json,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ColorWhen {
+    /// Colorize when stdout is a terminal and NO_COLOR is unset
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorWhen {
+    fn enabled(self) -> bool {
+        match self {
+            ColorWhen::Always => true,
+            ColorWhen::Never => false,
+            ColorWhen::Auto => {
+                io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+            }
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// List the builtin resolver presets, their addresses and transports, and the system resolver
+    ListResolvers,
+    /// Decode a result file written with --msgpack and print it as JSON
+    ReadMsgpack {
+        /// MessagePack result file
+        path: PathBuf,
+    },
+    /// Send a single query to the first --nameserver (or --resolver preset) and print the response
+    Query {
+        /// Name to query, e.g. version.bind
+        name: String,
+        /// Record type
+        #[arg(long = "type", value_name = "TYPE", default_value = "A")]
+        record_type: RecordType,
+        /// Query class; CH (CHAOS) is for server fingerprinting queries like version.bind TXT
+        #[arg(long, value_enum, ignore_case = true, default_value = "in")]
+        class: QueryClass,
+    },
+    /// Probe common subdomains of a wildcard zone and report which get the wildcard's answer and
+    /// which have records of their own
+    WildcardScope {
+        /// Zone to probe, e.g. example.com
+        domain: String,
+        /// Labels to probe instead of the builtin list of common subdomains
+        #[arg(long, value_name = "LABEL,...", value_delimiter = ',')]
+        labels: Vec<String>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum QueryClass {
+    /// Internet
+    In,
+    /// CHAOS
+    Ch,
+}
+
+impl From<QueryClass> for DNSClass {
+    fn from(class: QueryClass) -> Self {
+        match class {
+            QueryClass::In => DNSClass::IN,
+            QueryClass::Ch => DNSClass::CH,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+enum ResolverPreset {
+    /// Cloudflare (1.1.1.1)
+    #[default]
+    Cloudflare,
+    /// Google Public DNS (8.8.8.8)
+    Google,
+    /// Quad9 (9.9.9.9)
+    Quad9,
+    /// The nameservers from the system configuration (e.g. /etc/resolv.conf)
+    System,
+}
+
+impl ResolverPreset {
+    fn config(self) -> Result<ResolverConfig, String> {
+        match self {
+            Self::Cloudflare => Ok(ResolverConfig::cloudflare()),
+            Self::Google => Ok(ResolverConfig::google()),
+            Self::Quad9 => Ok(ResolverConfig::quad9()),
+            Self::System => trust_dns_resolver::system_conf::read_system_conf()
+                .map(|(config, _)| config)
+                .map_err(|e| format!("failed to read system resolver configuration: {}", e)),
+        }
+    }
+}
+
+/// A second resolver to consult when the primary one is inconclusive.
+#[derive(Debug, Clone, Copy)]
+enum FallbackResolver {
+    Preset(ResolverPreset),
+    Nameserver(SocketAddr),
+}
+
+impl FallbackResolver {
+    fn config(self) -> Result<ResolverConfig, String> {
+        match self {
+            Self::Preset(preset) => preset.config(),
+            Self::Nameserver(addr) => Ok(resolver_config(&[addr])),
+        }
+    }
+}
+
+fn parse_fallback_resolver(value: &str) -> Result<FallbackResolver, String> {
+    if let Ok(preset) = ResolverPreset::from_str(value, true) {
+        return Ok(FallbackResolver::Preset(preset));
+    }
+    parse_nameserver(value)
+        .map(FallbackResolver::Nameserver)
+        .map_err(|_| format!("expected a resolver preset or IP[:PORT], got '{}'", value))
+}
+
+/// Prints each resolver preset with its addresses and the transports used for each, marking the
+/// one a check would use with the given flags.
+fn list_resolvers(selected: ResolverPreset, nameservers: &[SocketAddr]) {
+    for preset in ResolverPreset::value_variants() {
+        let name = preset.to_possible_value().map(|v| v.get_name().to_string()).unwrap_or_default();
+        let marker = if *preset == selected && nameservers.is_empty() { " (selected)" } else { "" };
+        println!("{}{}", name, marker);
+        match preset.config() {
+            Ok(config) => {
+                let mut transports: Vec<(SocketAddr, Vec<String>)> = Vec::new();
+                for ns in config.name_servers() {
+                    let protocol = ns.protocol.to_string();
+                    match transports.iter_mut().find(|(addr, _)| *addr == ns.socket_addr) {
+                        Some((_, protocols)) => protocols.push(protocol),
+                        None => transports.push((ns.socket_addr, vec![protocol])),
+                    }
+                }
+                for (addr, protocols) in transports {
+                    println!("  {}\t{}", addr, protocols.join(", "));
+                }
+            }
+            Err(e) => println!("  unavailable: {}", e),
+        }
+    }
+    if !nameservers.is_empty() {
+        println!("custom (selected via --nameserver)");
+        for addr in nameservers {
+            println!("  {}\tudp, tcp", addr);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ExitCondition {
+    Registered,
+    Unregistered,
+    Errors,
+    Unknown,
+    IpMismatch,
+    CaaNoncompliant,
+    Blocklisted,
+    AssertionFailed,
+}
+
+impl ExitCondition {
+    /// How many domains meet the condition, and how to describe them.
+    fn count(self, result: &CheckResult) -> (usize, &'static str) {
+        let matching = |predicate: fn(&DomainStatus) -> bool| result.domains.iter().filter(|d| predicate(d)).count();
+        match self {
+            Self::Registered => (result.summary.registered, "are registered"),
+            Self::Unregistered => (result.summary.unregistered, "are unregistered"),
+            Self::Errors => (result.summary.errors, "could not be checked"),
+            Self::Unknown => (result.summary.unknown, "have an unknown status"),
+            Self::IpMismatch => (
+                matching(|d| d.ip_matches_expected == Some(false)),
+                "did not resolve to their expected IPs",
+            ),
+            Self::CaaNoncompliant => (matching(|d| d.caa_compliant == Some(false)), "are not CAA compliant"),
+            Self::Blocklisted => (matching(|d| d.blocklisted_ip), "resolved to a blocklisted IP"),
+            Self::AssertionFailed => (
+                matching(|d| d.assertions.iter().any(|assertion| !assertion.passed)),
+                "failed an --assert rule",
+            ),
+        }
+    }
+}
+
+fn parse_exit_code_on(value: &str) -> Result<(ExitCondition, i32), String> {
+    let (condition, code) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected CONDITION=CODE, got '{}'", value))?;
+    let condition = ExitCondition::from_str(condition.trim(), true)?;
+    let code = code
+        .trim()
+        .parse::<i32>()
+        .map_err(|_| format!("invalid exit code '{}'", code))?;
+    Ok((condition, code))
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+enum RegisteredRequires {
+    /// NS records
+    Ns,
+    /// A or AAAA records
+    Ip,
+    /// NS or address records
+    #[default]
+    Either,
+    /// NS and address records
+    Both,
+}
+
+impl RegisteredRequires {
+    fn registered(self, has_dns: bool, has_ip: bool) -> bool {
+        match self {
+            Self::Ns => has_dns,
+            Self::Ip => has_ip,
+            Self::Either => has_dns || has_ip,
+            Self::Both => has_dns && has_ip,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+enum IpVersion {
+    /// A records only
+    V4,
+    /// AAAA records only
+    V6,
+    /// A and AAAA records
+    #[default]
+    Both,
+}
+
+/// A source of registration verdicts for --source-precedence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum VerdictSource {
+    /// NS and address lookups
+    Dns,
+    /// The registry's RDAP server: a domain record means registered, "not found" means available
+    Rdap,
+}
+
+impl VerdictSource {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Dns => "dns",
+            Self::Rdap => "rdap",
+        }
+    }
+
+    /// This source's verdict, if it gave a definite one.
+    fn verdict(self, status: &DomainStatus) -> Option<bool> {
+        match self {
+            Self::Dns if status.unknown || status.inconclusive() => None,
+            Self::Dns if !status.registered && status.error.is_some() => None,
+            Self::Dns => Some(status.registered),
+            Self::Rdap => status.rdap_registered,
+        }
+    }
+}
+
+/// A region for --vantage, stood in for by an address in that AWS region's published ranges.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Vantage {
+    /// N. Virginia
+    UsEast,
+    /// Oregon
+    UsWest,
+    /// São Paulo
+    SaEast,
+    /// Ireland
+    EuWest,
+    /// Frankfurt
+    EuCentral,
+    /// Mumbai
+    ApSouth,
+    /// Singapore
+    ApSoutheast,
+    /// Tokyo
+    ApNortheast,
+    /// Sydney
+    Au,
+}
+
+impl Vantage {
+    fn name(self) -> &'static str {
+        match self {
+            Self::UsEast => "us-east",
+            Self::UsWest => "us-west",
+            Self::SaEast => "sa-east",
+            Self::EuWest => "eu-west",
+            Self::EuCentral => "eu-central",
+            Self::ApSouth => "ap-south",
+            Self::ApSoutheast => "ap-southeast",
+            Self::ApNortheast => "ap-northeast",
+            Self::Au => "au",
+        }
+    }
+
+    /// The /24 sent as the EDNS client subnet for this region.
+    fn client_subnet(self) -> ClientSubnet {
+        let network = match self {
+            Self::UsEast => Ipv4Addr::new(3, 80, 0, 0),
+            Self::UsWest => Ipv4Addr::new(54, 184, 0, 0),
+            Self::SaEast => Ipv4Addr::new(18, 228, 0, 0),
+            Self::EuWest => Ipv4Addr::new(54, 216, 0, 0),
+            Self::EuCentral => Ipv4Addr::new(18, 184, 0, 0),
+            Self::ApSouth => Ipv4Addr::new(13, 232, 0, 0),
+            Self::ApSoutheast => Ipv4Addr::new(13, 228, 0, 0),
+            Self::ApNortheast => Ipv4Addr::new(13, 112, 0, 0),
+            Self::Au => Ipv4Addr::new(13, 236, 0, 0),
+        };
+        ClientSubnet::new(IpAddr::V4(network), 24, 0)
+    }
+}
+
+fn parse_seconds(value: &str) -> Result<Duration, String> {
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|seconds| seconds.is_finite() && *seconds > 0.0)
+        .map(Duration::from_secs_f64)
+        .ok_or_else(|| format!("expected a positive number of seconds, got '{}'", value))
+}
+
+fn parse_nameserver(value: &str) -> Result<SocketAddr, String> {
+    if let Ok(addr) = value.parse::<SocketAddr>() {
+        return Ok(addr);
+    }
+    value
+        .parse::<IpAddr>()
+        .map(|ip| SocketAddr::new(ip, 53))
+        .map_err(|_| format!("invalid nameserver address: {}", value))
+}
+
+/// Seconds our clock may differ from the server's before it rejects a TSIG-signed query.
+const TSIG_FUDGE: u16 = 300;
+
+/// A --tsig-key. Debug shows only the key name, so the secret stays out of logs.
+#[derive(Clone)]
+struct TsigKey(TSigner);
+
+impl std::fmt::Debug for TsigKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("TsigKey").field(&self.0.signer_name().to_string()).finish()
+    }
+}
+
+fn parse_tsig_key(value: &str) -> Result<TsigKey, String> {
+    let (rest, secret) = value
+        .rsplit_once(':')
+        .ok_or_else(|| format!("expected [ALGORITHM:]NAME:SECRET, got '{}'", value))?;
+    let (algorithm, name) = rest.split_once(':').unwrap_or(("hmac-sha256", rest));
+    let algorithm = match algorithm.to_ascii_lowercase().as_str() {
+        "hmac-sha256" => TsigAlgorithm::HmacSha256,
+        "hmac-sha384" => TsigAlgorithm::HmacSha384,
+        "hmac-sha512" => TsigAlgorithm::HmacSha512,
+        other => return Err(format!("unsupported TSIG algorithm '{}' (expected hmac-sha256, hmac-sha384 or hmac-sha512)", other)),
+    };
+    let name = Name::from_ascii(name).map_err(|e| format!("invalid TSIG key name '{}': {}", name, e))?;
+    let secret = data_encoding::BASE64
+        .decode(secret.as_bytes())
+        .map_err(|e| format!("TSIG secret isn't valid base64: {}", e))?;
+    TSigner::new(secret, algorithm, name, TSIG_FUDGE).map(TsigKey).map_err(|e| e.to_string())
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct DomainStatus {
+    domain: String,
+    registered: bool,
+    has_dns: bool,
+    has_ip: bool,
+    nameservers: Vec<String>,
+    ip_addresses: Vec<String>,
+    error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error_kind: Option<String>,
+    #[serde(default)]
+    response_time_ms: u64,
+    /// When this domain's check completed (RFC 3339), unlike the run-level timestamp
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    checked_at: String,
+    #[serde(skip)]
+    nxdomain: bool,
+    #[serde(skip)]
+    servfail: bool,
+    #[serde(skip)]
+    refused: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    unknown: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    answered_by: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    consensus_confidence: Option<f64>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    uncertain: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    nxdomain_confirmed: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    decision: Option<Decision>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ip_matches_expected: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    metadata: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    blocklisted_ip: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    assertions: Vec<AssertionResult>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    parked: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    cnames: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cdn: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    txt_records: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    mx_records: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    spf: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    spf_includes: Vec<SpfInclude>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    dmarc_policy: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    dname: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    naptr_records: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    caa_records: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    caa_compliant: Option<bool>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    nameserver_ips: Vec<NameserverIps>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    reachable_nameservers: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    unreachable_nameservers: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ns_consistent: Option<bool>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    divergent_nameservers: Vec<DivergentNameserver>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    vantages: Vec<VantageAnswer>,
+    /// The --vantage regions that answered didn't all get the same addresses
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    geo_variance: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    full_response: Option<FullResponse>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    authoritative: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cert_expiry: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    redirects_to_https: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    http_error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    registrar: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expires_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    drop_eta: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rdap_error: Option<String>,
+    /// Whether the RDAP server had a record of the domain, when it was asked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rdap_registered: Option<bool>,
+    /// The --source-precedence source the verdict was taken from
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    verdict_source: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<String>,
+}
+
+/// Every section of a DNS response, as zone-file lines.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct FullResponse {
+    server: String,
+    response_code: String,
+    #[serde(default)]
+    authoritative: bool,
+    answers: Vec<String>,
+    authority: Vec<String>,
+    additional: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl FullResponse {
+    fn from_message(server: SocketAddr, message: &Message) -> Self {
+        let lines = |records: &[trust_dns_resolver::proto::rr::Record]| {
+            records.iter().map(|record| record.to_string()).collect()
+        };
+        Self {
+            server: server.to_string(),
+            response_code: message.response_code().to_string(),
+            authoritative: message.authoritative(),
+            answers: lines(message.answers()),
+            authority: lines(message.name_servers()),
+            additional: lines(message.additionals()),
+            error: None,
+        }
+    }
+}
+
+/// Raw signals behind a registered verdict, for consumers that want to classify on their own.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Decision {
+    ns_present: bool,
+    a_present: bool,
+    aaaa_present: bool,
+    nxdomain: bool,
+    /// A random sibling label gets the same address answer, so it may come from the parent's wildcard
+    wildcard: bool,
+}
+
+/// The SPF record an `include:` term points to; `spf` is None when there is none, which makes
+/// the including record fail to evaluate.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SpfInclude {
+    domain: String,
+    spf: Option<String>,
+}
+
+/// The `v=spf1` record among a name's TXT records.
+fn find_spf(txt_records: &[String]) -> Option<String> {
+    txt_records
+        .iter()
+        .find(|txt| {
+            let version = txt.split_whitespace().next().unwrap_or_default();
+            version.eq_ignore_ascii_case("v=spf1")
+        })
+        .cloned()
+}
+
+/// Domains named by the `include:` terms of an SPF record.
+fn spf_includes(spf: &str) -> Vec<String> {
+    spf.split_whitespace()
+        .filter_map(|term| {
+            let term = term.trim_start_matches(['+', '-', '~', '?']);
+            let (mechanism, domain) = term.split_once(':')?;
+            mechanism.eq_ignore_ascii_case("include").then(|| domain.to_string())
+        })
+        .collect()
+}
+
+/// The `p=` tag of the `v=DMARC1` record among `_dmarc` TXT records.
+fn dmarc_policy(txt_records: &[String]) -> Option<String> {
+    let record = txt_records.iter().find(|txt| {
+        let version = txt.split(';').next().unwrap_or_default().trim();
+        version.eq_ignore_ascii_case("v=DMARC1")
+    })?;
+    record.split(';').find_map(|tag| {
+        let (name, value) = tag.split_once('=')?;
+        name.trim().eq_ignore_ascii_case("p").then(|| value.trim().to_ascii_lowercase())
+    })
+}
+
+/// The addresses one --vantage region got for a domain.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct VantageAnswer {
+    region: String,
+    ips: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// A nameserver whose A and NS records differ from what most of the domain's nameservers serve.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct DivergentNameserver {
+    ns: String,
+    records: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct NameserverIps {
+    ns: String,
+    ips: Vec<String>,
+}
+
+impl DomainStatus {
+    fn new(domain: String) -> Self {
+        Self {
+            domain,
+            registered: false,
+            has_dns: false,
+            has_ip: false,
+            nameservers: Vec::new(),
+            ip_addresses: Vec::new(),
+            error: None,
+            error_kind: None,
+            response_time_ms: 0,
+            checked_at: String::new(),
+            nxdomain: false,
+            servfail: false,
+            refused: false,
+            unknown: false,
+            answered_by: None,
+            consensus_confidence: None,
+            uncertain: false,
+            nxdomain_confirmed: None,
+            decision: None,
+            ip_matches_expected: None,
+            metadata: None,
+            blocklisted_ip: false,
+            assertions: Vec::new(),
+            parked: false,
+            cnames: Vec::new(),
+            cdn: None,
+            txt_records: Vec::new(),
+            mx_records: Vec::new(),
+            spf: None,
+            spf_includes: Vec::new(),
+            dmarc_policy: None,
+            dname: None,
+            naptr_records: Vec::new(),
+            caa_records: Vec::new(),
+            caa_compliant: None,
+            nameserver_ips: Vec::new(),
+            reachable_nameservers: Vec::new(),
+            unreachable_nameservers: Vec::new(),
+            ns_consistent: None,
+            divergent_nameservers: Vec::new(),
+            vantages: Vec::new(),
+            geo_variance: false,
+            full_response: None,
+            authoritative: None,
+            cert_expiry: None,
+            redirects_to_https: false,
+            http_error: None,
+            registrar: None,
+            expires_at: None,
+            drop_eta: None,
+            rdap_error: None,
+            rdap_registered: None,
+            verdict_source: String::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    fn invalid(domain: String, reason: String) -> Self {
+        let mut status = Self::new(domain);
+        status.error = Some(format!("Invalid domain: {}", reason));
+        status.error_kind = Some("invalid_syntax".to_string());
+        status
+    }
+
+    /// Stamps the completion time, keeping the recorded one on replayed results.
+    fn mark_checked(&mut self) {
+        if self.checked_at.is_empty() {
+            self.checked_at = Utc::now().to_rfc3339();
+        }
+    }
+
+    fn deadline_exceeded(domain: String, deadline: Duration) -> Self {
+        let mut status = Self::new(domain);
+        status.error = Some(format!("Check exceeded the {} ms domain deadline", deadline.as_millis()));
+        status.error_kind = Some("domain_deadline".to_string());
+        status.response_time_ms = deadline.as_millis() as u64;
+        status
+    }
+
+    /// True when the lookups neither found the domain nor got a definite negative answer.
+    fn inconclusive(&self) -> bool {
+        !self.registered && (self.servfail || self.refused || self.error_kind.as_deref() == Some("timeout"))
+    }
+
+    /// True when the check got no usable answer: an error, an unknown verdict, or a SERVFAIL or
+    /// REFUSED that the resolver reports as an empty answer rather than an error.
+    fn failed(&self) -> bool {
+        let errored = self.error.is_some() && self.error_kind.as_deref() != Some("invalid_syntax");
+        errored || self.unknown || self.inconclusive()
+    }
+
+    /// True when anything casts doubt on the verdict. Entries rejected as invalid aren't in doubt.
+    fn is_uncertain(&self) -> bool {
+        let failed = self.error.is_some() && self.error_kind.as_deref() != Some("invalid_syntax");
+        self.inconclusive()
+            || failed
+            || self.unknown
+            || self.uncertain
+            || !self.warnings.is_empty()
+            || self.decision.as_ref().is_some_and(|decision| decision.wildcard)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CheckResult {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    timestamp: String,
+    check_count: usize,
+    #[serde(default)]
+    config: RunConfig,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    manifest: Option<Manifest>,
+    domains: Vec<DomainStatus>,
+    summary: ResultSummary,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    stats: Option<RunStats>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    estimate: Option<SampleEstimate>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    infra_report: Option<InfraReport>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    registrars: Option<Vec<RegistrarGroup>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    input_files: Vec<InputFileSummary>,
+}
+
+/// Summary of the domains read from one `--input-file`.
+#[derive(Debug, Serialize, Deserialize)]
+struct InputFileSummary {
+    path: String,
+    summary: ResultSummary,
+}
+
+/// Infrastructure shared across registered domains, most widely shared first.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct InfraReport {
+    nameservers: Vec<SharedInfra>,
+    ip_addresses: Vec<SharedInfra>,
+    mx_records: Vec<SharedInfra>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SharedInfra {
+    value: String,
+    count: usize,
+    domains: Vec<String>,
+}
+
+impl InfraReport {
+    fn from_domains(domains: &[DomainStatus]) -> Self {
+        let registered: Vec<&DomainStatus> = domains.iter().filter(|d| d.registered).collect();
+        let shared = |values: fn(&DomainStatus) -> &[String]| {
+            let mut by_value: BTreeMap<String, Vec<String>> = BTreeMap::new();
+            for status in &registered {
+                for value in values(status) {
+                    let holders = by_value.entry(value.trim_end_matches('.').to_ascii_lowercase()).or_default();
+                    if !holders.contains(&status.domain) {
+                        holders.push(status.domain.clone());
+                    }
+                }
+            }
+            let mut shared: Vec<SharedInfra> = by_value
+                .into_iter()
+                .filter(|(_, domains)| domains.len() > 1)
+                .map(|(value, domains)| SharedInfra { value, count: domains.len(), domains })
+                .collect();
+            // Stable sort keeps equally shared values in alphabetical order
+            shared.sort_by_key(|infra| std::cmp::Reverse(infra.count));
+            shared
+        };
+        Self {
+            nameservers: shared(|d| &d.nameservers),
+            ip_addresses: shared(|d| &d.ip_addresses),
+            mx_records: shared(|d| &d.mx_records),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RegistrarGroup {
+    registrar: String,
+    count: usize,
+    domains: Vec<String>,
+}
+
+impl RegistrarGroup {
+    fn from_domains(domains: &[DomainStatus]) -> Vec<Self> {
+        let mut by_registrar: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+        for status in domains {
+            if let Some(registrar) = &status.registrar {
+                by_registrar.entry(registrar).or_default().push(status.domain.clone());
+            }
+        }
+        let mut groups: Vec<Self> = by_registrar
+            .into_iter()
+            .map(|(registrar, domains)| Self { registrar: registrar.to_string(), count: domains.len(), domains })
+            .collect();
+        groups.sort_by_key(|group| std::cmp::Reverse(group.count));
+        groups
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RunStats {
+    latency_ms: LatencyStats,
+    /// Nearly every domain failed, which points at the resolver or its configuration
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    systemic_failure: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct LatencyStats {
+    count: usize,
+    min: f64,
+    mean: f64,
+    max: f64,
+    p50: f64,
+    p90: f64,
+    p99: f64,
+}
+
+/// Streams response times into a t-digest so percentiles need bounded memory however long the run.
+struct LatencyAggregator {
+    digest: TDigest,
+}
+
+impl LatencyAggregator {
+    fn new() -> Self {
+        Self {
+            digest: TDigest::new_with_size(200),
+        }
+    }
+
+    fn record(&mut self, status: &DomainStatus) {
+        // Entries rejected before lookup have no latency to report
+        if status.error_kind.as_deref() != Some("invalid_syntax") {
+            self.digest.push(status.response_time_ms as f64);
+        }
+    }
+
+    fn stats(&mut self) -> Option<RunStats> {
+        self.digest.flush();
+        let quantile = |q| self.digest.estimate_quantile(q).unwrap_or(0.0);
+        Some(RunStats {
+            latency_ms: LatencyStats {
+                count: self.digest.count() as usize,
+                min: self.digest.min()?,
+                mean: self.digest.mean()?,
+                max: self.digest.max()?,
+                p50: quantile(0.5),
+                p90: quantile(0.9),
+                p99: quantile(0.99),
+            },
+            systemic_failure: false,
+        })
+    }
+}
+
+/// Bars of a --latency-sparkline, lowest first; the lowest also marks an empty bucket.
+const SPARK_BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Most buckets a --latency-sparkline splits the response time range into.
+const SPARK_BUCKETS: u64 = 24;
+
+/// Renders the response time distribution as `min ▁▃█▅▂ max`, one bar per equal-width bucket,
+/// scaled to the fullest bucket.
+fn latency_sparkline(domains: &[DomainStatus]) -> Option<String> {
+    let times: Vec<u64> = domains
+        .iter()
+        .filter(|d| d.error_kind.as_deref() != Some("invalid_syntax"))
+        .map(|d| d.response_time_ms)
+        .collect();
+    let min = *times.iter().min()?;
+    let max = *times.iter().max()?;
+    let buckets = (max - min + 1).min(SPARK_BUCKETS);
+    let width = (max - min + 1).div_ceil(buckets);
+    let mut counts = vec![0usize; buckets as usize];
+    for time in &times {
+        counts[((time - min) / width) as usize] += 1;
+    }
+    let fullest = *counts.iter().max()?;
+    let bars: String = counts
+        .iter()
+        .map(|&count| match count {
+            0 => SPARK_BARS[0],
+            _ => SPARK_BARS[1 + (count * (SPARK_BARS.len() - 1) - 1) / fullest],
+        })
+        .collect();
+    Some(format!("Latency: {}ms {} {}ms ({} samples)", min, bars, max, times.len()))
+}
+
+/// z-score for the 95% confidence interval of a --sample estimate.
+const SAMPLE_Z: f64 = 1.96;
+
+/// Registration rate of the whole input, estimated from a `--sample` of it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SampleEstimate {
+    population: usize,
+    sampled: usize,
+    /// Sampled domains with a verdict; errors and unknowns say nothing about the rate
+    decided: usize,
+    registered: usize,
+    registration_rate: f64,
+    confidence: f64,
+    ci_low: f64,
+    ci_high: f64,
+}
+
+impl SampleEstimate {
+    /// Wilson score interval, narrowed by the finite population correction since the sample is
+    /// drawn without replacement and may be a large share of the input.
+    fn from_domains(domains: &[DomainStatus], population: usize) -> Option<Self> {
+        let decided: Vec<&DomainStatus> =
+            domains.iter().filter(|d| d.registered || (d.error.is_none() && !d.unknown)).collect();
+        let registered = decided.iter().filter(|d| d.registered).count();
+        if decided.is_empty() {
+            return None;
+        }
+        let n = decided.len() as f64;
+        let p = registered as f64 / n;
+        let (ci_low, ci_high) = if decided.len() >= population {
+            (p, p)
+        } else {
+            let fpc = (population - decided.len()) as f64 / (population - 1) as f64;
+            let z2 = SAMPLE_Z * SAMPLE_Z * fpc;
+            let center = (p + z2 / (2.0 * n)) / (1.0 + z2 / n);
+            let margin = (z2 * (p * (1.0 - p) / n + z2 / (4.0 * n * n))).sqrt() / (1.0 + z2 / n);
+            ((center - margin).max(0.0), (center + margin).min(1.0))
+        };
+        Some(Self {
+            population,
+            sampled: domains.len(),
+            decided: decided.len(),
+            registered,
+            registration_rate: p,
+            confidence: 0.95,
+            ci_low,
+            ci_high,
+        })
+    }
+}
+
+/// Settings needed to reproduce a run.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct RunConfig {
+    seed: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sample: Option<usize>,
+}
+
+/// Audit trail tying a result file to the exact input and tool that produced it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Manifest {
+    input_sha256: String,
+    tool_version: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    started_at: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    finished_at: Option<String>,
+    arguments: Vec<String>,
+}
+
+impl Manifest {
+    fn new<'a>(domains: impl IntoIterator<Item = &'a String>, started_at: String) -> Self {
+        let mut hasher = Sha256::new();
+        for domain in domains {
+            hasher.update(domain.as_bytes());
+            hasher.update(b"\n");
+        }
+
+        Self {
+            input_sha256: format!("{:x}", hasher.finalize()),
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            started_at,
+            finished_at: None,
+            arguments: redact_arguments(std::env::args().skip(1)),
+        }
+    }
+}
+
+/// Flags whose values are credentials, kept out of the manifest's record of the command line.
+const SECRET_FLAGS: &[&str] = &["--tsig-key", "--import-token"];
+
+fn redact_arguments(args: impl Iterator<Item = String>) -> Vec<String> {
+    let mut redacted = Vec::new();
+    let mut secret_next = false;
+    for arg in args {
+        if std::mem::take(&mut secret_next) {
+            redacted.push("<redacted>".to_string());
+        } else if let Some(flag) = SECRET_FLAGS.iter().find(|flag| arg.starts_with(&format!("{}=", flag))) {
+            redacted.push(format!("{}=<redacted>", flag));
+        } else {
+            secret_next = SECRET_FLAGS.contains(&arg.as_str());
+            redacted.push(arg);
+        }
+    }
+    redacted
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ResultSummary {
+    total_checked: usize,
+    registered: usize,
+    unregistered: usize,
+    errors: usize,
+    #[serde(default)]
+    unknown: usize,
+}
+
+/// A line of the `--events` NDJSON protocol.
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Event<'a> {
+    Started { total: usize },
+    Result(&'a DomainStatus),
+    Progress { done: usize, total: usize },
+    Finished { summary: &'a ResultSummary },
+}
+
+impl Event<'_> {
+    /// The event as an SSE frame, named by its kind so pages can `addEventListener` per kind.
+    fn sse_frame(&self) -> serde_json::Result<String> {
+        let value = serde_json::to_value(self)?;
+        let kind = value.get("event").and_then(|kind| kind.as_str()).unwrap_or("message").to_string();
+        Ok(format!("event: {}\ndata: {}\n\n", kind, serde_json::to_string(&value)?))
+    }
+
+    /// Sends the event to --serve clients, if any.
+    async fn broadcast(&self, sse: &Option<(mpsc::Sender<String>, JoinHandle<()>)>) {
+        if let Some((tx, _)) = sse {
+            match self.sse_frame() {
+                Ok(frame) => {
+                    let _ = tx.send(frame).await;
+                }
+                Err(e) => eprintln!("Warning: failed to serialize event: {}", e),
+            }
+        }
+    }
+
+    fn emit(&self) {
+        match serde_json::to_string(self) {
+            // Rust's stdout is line-buffered even when piped, so each event reaches `tee` or a
+            // consumer as soon as it's printed. Don't wrap it in a BufWriter.
+            Ok(line) => println!("{}", line),
+            Err(e) => eprintln!("Warning: failed to serialize event: {}", e),
+        }
+    }
+}
+
+/// Results kept in the `--tui` scrollback.
+const DASHBOARD_RECENT: usize = 200;
+
+/// Consecutive failed lookups taken as a resolver outage rather than bad domains.
+const RECOVER_STREAK: usize = 20;
+
+/// Delay between connectivity probes while --auto-recover waits for the resolver.
+const RECOVER_PROBE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Live terminal view for `--tui`: progress, running counts, throughput and the latest results.
+/// Runs in the alternate screen without raw mode, so Ctrl-C still arrives as a signal. The
+/// terminal is restored on drop.
+struct Dashboard {
+    terminal: ratatui::Terminal<ratatui::backend::CrosstermBackend<io::Stdout>>,
+    total: usize,
+    /// Completed checks, kept current by the run's progress callbacks.
+    done: std::sync::Arc<AtomicUsize>,
+    registered: usize,
+    unregistered: usize,
+    errors: usize,
+    recent: std::collections::VecDeque<String>,
+    started: Instant,
+}
+
+impl Dashboard {
+    fn new(total: usize, done: std::sync::Arc<AtomicUsize>) -> io::Result<Self> {
+        ratatui::crossterm::execute!(io::stdout(), ratatui::crossterm::terminal::EnterAlternateScreen)?;
+        let mut terminal = ratatui::Terminal::new(ratatui::backend::CrosstermBackend::new(io::stdout()))?;
+        terminal.hide_cursor()?;
+        terminal.clear()?;
+        Ok(Self {
+            terminal,
+            total,
+            done,
+            registered: 0,
+            unregistered: 0,
+            errors: 0,
+            recent: std::collections::VecDeque::with_capacity(DASHBOARD_RECENT),
+            started: Instant::now(),
+        })
+    }
+
+    fn record(&mut self, status: &DomainStatus) {
+        let verdict = if let Some(error) = &status.error {
+            self.errors += 1;
+            format!("error: {}", error)
+        } else if status.registered {
+            self.registered += 1;
+            "registered".to_string()
+        } else {
+            self.unregistered += 1;
+            "unregistered".to_string()
+        };
+        if self.recent.len() == DASHBOARD_RECENT {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(format!("{}  {}  ({} ms)", status.domain, verdict, status.response_time_ms));
+    }
+
+    fn draw(&mut self) -> io::Result<()> {
+        use ratatui::layout::{Constraint, Layout};
+        use ratatui::widgets::{Block, Gauge, List, Paragraph};
+
+        let done = self.done.load(Ordering::Relaxed);
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let qps = if elapsed > 0.0 { done as f64 / elapsed } else { 0.0 };
+        let ratio = if self.total == 0 { 1.0 } else { (done as f64 / self.total as f64).min(1.0) };
+        let counts = format!(
+            "Registered: {}   Unregistered: {}   Errors: {}   QPS: {:.1}   Elapsed: {:.0}s",
+            self.registered, self.unregistered, self.errors, qps, elapsed
+        );
+        let progress = format!("{}/{}", done, self.total);
+        let recent: Vec<&str> = self.recent.iter().rev().map(String::as_str).collect();
+
+        self.terminal.draw(|frame| {
+            let [gauge, stats, list] =
+                Layout::vertical([Constraint::Length(3), Constraint::Length(3), Constraint::Min(0)]).areas(frame.area());
+            frame.render_widget(
+                Gauge::default().block(Block::bordered().title("Progress")).ratio(ratio).label(progress),
+                gauge,
+            );
+            frame.render_widget(Paragraph::new(counts).block(Block::bordered().title("Counts")), stats);
+            frame.render_widget(List::new(recent).block(Block::bordered().title("Recent Results")), list);
+        })?;
+        Ok(())
+    }
+}
+
+impl Drop for Dashboard {
+    fn drop(&mut self) {
+        let _ = self.terminal.show_cursor();
+        let _ = ratatui::crossterm::execute!(io::stdout(), ratatui::crossterm::terminal::LeaveAlternateScreen);
+    }
+}
+
+/// Called with (done, total) as domains complete, for embedders that want progress without parsing
+/// terminal output. The CLI calls it once each result has been emitted, which drives the `--events`
+/// progress lines and the `--tui` gauge.
+type ProgressCallback = std::sync::Arc<dyn Fn(usize, usize) + Send + Sync>;
+
+struct DomainChecker {
+    resolver: TokioAsyncResolver,
+    config: ResolverConfig,
+    opts: ResolverOpts,
+    servers: Vec<SocketAddr>,
+    options: CheckOptions,
+    tls_prober: Option<TlsProber>,
+    rdap: Option<RdapClient>,
+    zone: Option<Zone>,
+    adaptive: Option<AdaptiveLimit>,
+    latency_throttle: Option<LatencyThrottle>,
+    tld_limits: HashMap<String, usize>,
+    fallback: Option<Box<DomainChecker>>,
+    confirmer: Option<Box<DomainChecker>>,
+    /// One checker per nameserver for --consensus
+    voters: Vec<DomainChecker>,
+    replay: Option<HashMap<String, DomainStatus>>,
+}
+
+/// Optional per-domain lookups, set from the command line.
+#[derive(Debug, Clone, Default)]
+struct CheckOptions {
+    resolver: ResolverPreset,
+    fallback_resolver: Option<FallbackResolver>,
+    confirm_resolver: Option<FallbackResolver>,
+    consensus: Option<usize>,
+    timeouts: Timeouts,
+    registered_requires: RegisteredRequires,
+    decision: bool,
+    treat_parked_as_available: bool,
+    timeout_as_unknown: bool,
+    mx: bool,
+    rdap: bool,
+    source_precedence: Vec<VerdictSource>,
+    tsig: Option<TsigKey>,
+    ip_version: IpVersion,
+    txt: bool,
+    email_policy: bool,
+    dname: bool,
+    enum_all: bool,
+    caa_issuers: Vec<String>,
+    resolve_all_ns: bool,
+    check_ns_reachable: bool,
+    check_ns_consistency: bool,
+    /// --vantage-resolver and the --vantage regions to ask it as
+    vantages: Option<(SocketAddr, Vec<Vantage>)>,
+    tls_check: bool,
+    full_response: bool,
+    recursion_desired: bool,
+    fresh_ports: bool,
+    zone_file: Option<PathBuf>,
+    zone_origin: Option<String>,
+    tld_concurrency: Vec<(String, usize)>,
+    recheck_nxdomain: Option<Duration>,
+    domain_deadline: Option<Duration>,
+    adaptive: Option<(usize, usize)>,
+    target_latency: Option<Duration>,
+}
+
+impl From<&Cli> for CheckOptions {
+    fn from(cli: &Cli) -> Self {
+        Self {
+            resolver: cli.resolver,
+            fallback_resolver: cli.fallback_resolver,
+            confirm_resolver: cli.confirm_nxdomain.then(|| {
+                cli.confirm_resolver.unwrap_or_else(|| {
+                    let primary_is_google = cli.nameservers.is_empty() && cli.resolver == ResolverPreset::Google;
+                    FallbackResolver::Preset(if primary_is_google { ResolverPreset::Cloudflare } else { ResolverPreset::Google })
+                })
+            }),
+            consensus: cli.consensus.map(|n| n as usize),
+            timeouts: Timeouts::from(cli),
+            registered_requires: cli.registered_requires,
+            decision: cli.decision,
+            treat_parked_as_available: cli.treat_parked_as_available,
+            timeout_as_unknown: cli.timeout_as_unknown,
+            mx: cli.infra_report || cli.assertions.iter().any(|a| a.field == "mx_records"),
+            rdap: cli.rdap || cli.source_precedence.contains(&VerdictSource::Rdap),
+            source_precedence: cli.source_precedence.clone(),
+            tsig: cli.tsig_key.clone(),
+            ip_version: cli.ip_version,
+            email_policy: cli.email_policy,
+            dname: cli.dname,
+            enum_all: cli.enum_all,
+            caa_issuers: cli.caa_issuers.iter().map(|ca| ca.trim_end_matches('.').to_ascii_lowercase()).collect(),
+            txt: cli.txt
+                || cli.matches.iter().any(|m| m.field == "txt_records")
+                || cli.assertions.iter().any(|a| a.field == "txt_records"),
+            resolve_all_ns: cli.resolve_all_ns,
+            check_ns_reachable: cli.check_ns_reachable,
+            check_ns_consistency: cli.check_ns_consistency,
+            vantages: (!cli.vantages.is_empty()).then(|| (cli.vantage_resolver, cli.vantages.clone())),
+            tls_check: cli.tls_check,
+            full_response: cli.full_response,
+            recursion_desired: !cli.no_recursion_desired,
+            fresh_ports: cli.fresh_ports,
+            zone_file: cli.zone_file.clone(),
+            zone_origin: cli.zone_origin.clone(),
+            tld_concurrency: cli.tld_concurrency.clone(),
+            recheck_nxdomain: cli
+                .recheck_nxdomain
+                .then(|| Duration::from_millis(cli.recheck_delay_ms)),
+            domain_deadline: cli.domain_deadline.map(Duration::from_millis),
+            target_latency: cli.latency_adaptive.then(|| Duration::from_millis(cli.target_latency_ms)),
+            adaptive: cli
+                .adaptive
+                .then(|| (cli.min_concurrent.clamp(1, cli.concurrent.max(1)), cli.concurrent.max(1))),
+        }
+    }
+}
+
+/// How long to wait on servers. trust-dns has no separate connect timeout, so resolver
+/// lookups only honor `query`; `connect` covers the connections this tool opens itself.
+#[derive(Debug, Clone, Copy)]
+struct Timeouts {
+    query: Duration,
+    connect: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            query: Duration::from_secs(2),
+            connect: Duration::from_secs(2),
+        }
+    }
+}
+
+impl From<&Cli> for Timeouts {
+    fn from(cli: &Cli) -> Self {
+        Self {
+            query: cli.timeout,
+            connect: cli.connect_timeout.unwrap_or(cli.timeout),
+        }
+    }
+}
+
+fn resolver_opts(options: &CheckOptions) -> ResolverOpts {
+    let mut opts = ResolverOpts::default();
+    opts.timeout = options.timeouts.query;
+    opts.attempts = 2;
+    opts.recursion_desired = options.recursion_desired;
+    if options.recheck_nxdomain.is_some() {
+        // A cached NXDOMAIN would make the recheck pointless
+        opts.negative_max_ttl = Some(Duration::ZERO);
+    }
+    opts
+}
+
+fn resolver_config(nameservers: &[SocketAddr]) -> ResolverConfig {
+    let mut group = NameServerConfigGroup::with_capacity(nameservers.len() * 2);
+    for &addr in nameservers {
+        group.push(NameServerConfig::new(addr, Protocol::Udp));
+        group.push(NameServerConfig::new(addr, Protocol::Tcp));
+    }
+    ResolverConfig::from_parts(None, Vec::new(), group)
+}
+
+type RawQueryResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// Sends one query straight to `server` and returns the whole response message, including the
+/// sections the resolver API drops. Retries over TCP when the UDP answer is truncated.
+async fn raw_query(
+    server: SocketAddr,
+    query: Query,
+    recursion_desired: bool,
+    timeouts: Timeouts,
+    tsig: Option<&TsigKey>,
+) -> RawQueryResult<Message> {
+    raw_exchange(server, query_message(query, recursion_desired), timeouts, tsig).await
+}
+
+/// A query message with EDNS, ready for extra options before it's sent with `raw_exchange`.
+fn query_message(query: Query, recursion_desired: bool) -> Message {
+    let mut edns = Edns::new();
+    edns.set_max_payload(4096);
+
+    let mut request = Message::new();
+    request
+        .set_id(rand::random())
+        .set_message_type(MessageType::Query)
+        .set_op_code(OpCode::Query)
+        .set_recursion_desired(recursion_desired)
+        .set_edns(edns)
+        .add_query(query);
+    request
+}
+
+async fn raw_exchange(
+    server: SocketAddr,
+    mut request: Message,
+    timeouts: Timeouts,
+    tsig: Option<&TsigKey>,
+) -> RawQueryResult<Message> {
+    let verifier = match tsig {
+        Some(TsigKey(signer)) => request.finalize(signer, Utc::now().timestamp() as u32)?,
+        None => None,
+    };
+    let bytes = request.to_vec()?;
+
+    let mut response = tokio::time::timeout(timeouts.query, raw_query_udp(server, &bytes, request.id())).await??;
+    if Message::from_vec(&response)?.truncated() {
+        let stream = tokio::time::timeout(timeouts.connect, TcpStream::connect(server))
+            .await
+            .map_err(|_| format!("connect to {} timed out", server))??;
+        response = tokio::time::timeout(timeouts.query, raw_query_tcp(stream, &bytes)).await??;
+    }
+    // The signature covers the exact bytes received, so verify before decoding
+    match verifier {
+        Some(mut verify) => Ok(verify(&response)?.into_message()),
+        None => Ok(Message::from_vec(&response)?),
+    }
+}
+
+/// Sends the query and returns the raw bytes of the matching response.
+async fn raw_query_udp(server: SocketAddr, bytes: &[u8], id: u16) -> RawQueryResult<Vec<u8>> {
+    let bind: SocketAddr = if server.is_ipv4() {
+        (Ipv4Addr::UNSPECIFIED, 0).into()
+    } else {
+        (Ipv6Addr::UNSPECIFIED, 0).into()
+    };
+    let socket = UdpSocket::bind(bind).await?;
+    socket.send_to(bytes, server).await?;
+
+    let mut buf = [0u8; 4096];
+    loop {
+        let (len, from) = socket.recv_from(&mut buf).await?;
+        if from != server {
+            continue;
+        }
+        let message = Message::from_vec(&buf[..len])?;
+        if message.id() == id {
+            return Ok(buf[..len].to_vec());
+        }
+    }
+}
+
+async fn raw_query_tcp(mut stream: TcpStream, bytes: &[u8]) -> RawQueryResult<Vec<u8>> {
+    stream.write_all(&u16::try_from(bytes.len())?.to_be_bytes()).await?;
+    stream.write_all(bytes).await?;
+
+    let mut len = [0u8; 2];
+    stream.read_exact(&mut len).await?;
+    let mut buf = vec![0u8; u16::from_be_bytes(len) as usize];
+    stream.read_exact(&mut buf).await?;
+    Ok(buf)
+}
+
+/// Returns true if the nameserver answers a root NS query at all, even negatively.
+async fn probe_nameserver(addr: SocketAddr, opts: ResolverOpts) -> bool {
+    let resolver = TokioAsyncResolver::tokio(resolver_config(&[addr]), opts);
+    match resolver.ns_lookup(".").await {
+        Ok(_) => true,
+        Err(e) => matches!(
+            e.kind(),
+            ResolveErrorKind::NoRecordsFound { .. }
+        ),
+    }
+}
+
+/// Additive-increase/multiplicative-decrease concurrency limit for `--adaptive`. After every
+/// window of `limit` checks the limit halves if more than a fifth errored, or grows by one if
+/// none did, always staying within `min..=max`.
+struct AdaptiveLimit {
+    semaphore: Semaphore,
+    state: std::sync::Mutex<AdaptiveState>,
+    min: usize,
+    max: usize,
+}
+
+struct AdaptiveState {
+    limit: usize,
+    completed: usize,
+    errors: usize,
+    /// Permits still to retire because they were in use when the limit shrank
+    debt: usize,
+}
+
+impl AdaptiveLimit {
+    fn new(min: usize, max: usize) -> Self {
+        Self {
+            semaphore: Semaphore::new(max),
+            state: std::sync::Mutex::new(AdaptiveState {
+                limit: max,
+                completed: 0,
+                errors: 0,
+                debt: 0,
+            }),
+            min,
+            max,
+        }
+    }
+
+    async fn acquire(&self) -> Option<SemaphorePermit<'_>> {
+        self.semaphore.acquire().await.ok()
+    }
+
+    fn release(&self, permit: Option<SemaphorePermit<'_>>, errored: bool) {
+        let mut state = self.state.lock().unwrap();
+
+        if let Some(permit) = permit {
+            if state.debt > 0 {
+                state.debt -= 1;
+                permit.forget();
+            }
+        }
+
+        state.completed += 1;
+        state.errors += usize::from(errored);
+        if state.completed < state.limit {
+            return;
+        }
+
+        let limit = state.limit;
+        if state.errors * 5 > state.completed {
+            let lowered = (limit / 2).max(self.min);
+            let retired = self.semaphore.forget_permits(limit - lowered);
+            state.debt += limit - lowered - retired;
+            state.limit = lowered;
+        } else if state.errors == 0 && limit < self.max {
+            if state.debt > 0 {
+                state.debt -= 1;
+            } else {
+                self.semaphore.add_permits(1);
+            }
+            state.limit = limit + 1;
+        }
+        state.completed = 0;
+        state.errors = 0;
+    }
+}
+
+/// Longest gap --latency-adaptive puts between the starts of two checks.
+const MAX_CHECK_SPACING: Duration = Duration::from_secs(5);
+
+/// How often --latency-adaptive may change the spacing, so one change can take effect before
+/// the next is decided.
+const SPACING_ADJUST_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Spacing between check starts for `--latency-adaptive`, driven by an exponentially weighted
+/// average of response times: doubled while the average is above the target, halved once it's
+/// back under four fifths of it.
+struct LatencyThrottle {
+    target_ms: f64,
+    state: std::sync::Mutex<ThrottleState>,
+}
+
+struct ThrottleState {
+    average_ms: Option<f64>,
+    spacing: Duration,
+    next_start: Instant,
+    last_adjusted: Instant,
+}
+
+impl LatencyThrottle {
+    fn new(target: Duration) -> Self {
+        let now = Instant::now();
+        Self {
+            target_ms: target.as_millis() as f64,
+            state: std::sync::Mutex::new(ThrottleState {
+                average_ms: None,
+                spacing: Duration::ZERO,
+                next_start: now,
+                last_adjusted: now,
+            }),
+        }
+    }
+
+    /// Waits for this check's turn, reserving the next slot one spacing later.
+    async fn wait(&self) {
+        let start = {
+            let mut state = self.state.lock().unwrap();
+            let start = state.next_start.max(Instant::now());
+            state.next_start = start + state.spacing;
+            start
+        };
+        tokio::time::sleep_until(start.into()).await;
+    }
+
+    fn record(&self, status: &DomainStatus) {
+        // Entries rejected before any lookup say nothing about the resolver
+        if status.error_kind.as_deref() == Some("invalid_syntax") {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        let sample = status.response_time_ms as f64;
+        let average = state.average_ms.map_or(sample, |average| average * 0.9 + sample * 0.1);
+        state.average_ms = Some(average);
+
+        if state.last_adjusted.elapsed() < SPACING_ADJUST_INTERVAL {
+            return;
+        }
+        if average > self.target_ms {
+            state.spacing = (state.spacing * 2).clamp(Duration::from_millis(10), MAX_CHECK_SPACING);
+        } else if average < self.target_ms * 0.8 && !state.spacing.is_zero() {
+            state.spacing /= 2;
+            if state.spacing < Duration::from_millis(1) {
+                state.spacing = Duration::ZERO;
+            }
+        }
+        state.last_adjusted = Instant::now();
+    }
+}
+
+/// Records parsed from a zone file, used in place of live DNS for pre-deployment checks.
+struct Zone {
+    records: BTreeMap<RrKey, RecordSet>,
+}
+
+impl Zone {
+    fn load(path: &Path, origin: Option<&str>) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let origin = origin
+            .map(Name::from_utf8)
+            .transpose()
+            .map_err(|e| format!("invalid zone origin: {}", e))?;
+        let (_, records) = ZoneParser::new()
+            .parse(Lexer::new(&text), origin)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(Self { records })
+    }
+
+    fn name(domain: &str) -> Option<LowerName> {
+        let fqdn = format!("{}.", domain.trim_end_matches('.'));
+        Name::from_utf8(fqdn).ok().map(LowerName::from)
+    }
+
+    fn contains(&self, domain: &str) -> bool {
+        Self::name(domain).is_some_and(|name| self.records.keys().any(|key| key.name == name))
+    }
+
+    fn rdata(&self, domain: &str, record_type: RecordType) -> Vec<String> {
+        self.records(domain, record_type).iter().map(|data| data.to_string()).collect()
+    }
+
+    fn records(&self, domain: &str, record_type: RecordType) -> Vec<RData> {
+        Self::name(domain)
+            .and_then(|name| self.records.get(&RrKey::new(name, record_type)))
+            .map(|set| set.records_without_rrsigs().filter_map(|record| record.data().cloned()).collect())
+            .unwrap_or_default()
+    }
+}
+
+/// Collects the statuses out of --json-map or --json-by-tld output, where any object without a
+/// `domain` field is a level of nesting.
+fn flatten_keyed_domains(map: serde_json::Map<String, serde_json::Value>, domains: &mut Vec<serde_json::Value>) {
+    for (_, value) in map {
+        match value {
+            serde_json::Value::Object(nested) if !nested.contains_key("domain") => flatten_keyed_domains(nested, domains),
+            status => domains.push(status),
+        }
+    }
+}
+
+/// Reads the domains of a saved result, written either as JSON (array, --json-map or
+/// --json-by-tld object) or as --msgpack.
+fn read_replay(path: &Path) -> Result<Vec<DomainStatus>, String> {
+    let bytes = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let result = match serde_json::from_slice::<serde_json::Value>(&bytes) {
+        Ok(mut value) => {
+            if let Some(serde_json::Value::Object(map)) = value.get_mut("domains") {
+                let mut domains = Vec::new();
+                flatten_keyed_domains(std::mem::take(map), &mut domains);
+                value["domains"] = serde_json::Value::Array(domains);
+            }
+            serde_json::from_value::<CheckResult>(value).map_err(|e| e.to_string())
+        }
+        Err(_) => rmp_serde::from_slice::<CheckResult>(&bytes).map_err(|e| e.to_string()),
+    };
+    result
+        .map(|result| result.domains)
+        .map_err(|e| format!("{}: not a saved result: {}", path.display(), e))
+}
+
+/// Nameserver domains used by registrars and marketplaces to park domains that are for sale.
+const PARKING_NAMESERVERS: &[&str] = &[
+    "above.com",
+    "afternic.com",
+    "bodis.com",
+    "dan.com",
+    "hugedomains.com",
+    "parkingcrew.net",
+    "parklogic.com",
+    "sedoparking.com",
+    "uniregistrymarket.link",
+];
+
+fn is_parking_nameserver(ns: &str) -> bool {
+    let ns = ns.trim_end_matches('.').to_ascii_lowercase();
+    PARKING_NAMESERVERS
+        .iter()
+        .any(|parking| ns == *parking || ns.ends_with(&format!(".{}", parking)))
+}
+
+/// A result whose lookups failed outright, as opposed to a definite answer or a rejected input.
+fn is_lookup_failure(status: &DomainStatus) -> bool {
+    status.error.is_some() && status.error_kind.as_deref() != Some("invalid_syntax")
+}
+
+fn is_timeout(e: &ResolveError) -> bool {
+    match e.kind() {
+        ResolveErrorKind::Timeout => true,
+        ResolveErrorKind::Proto(proto) => matches!(proto.kind(), ProtoErrorKind::Timeout),
+        _ => false,
+    }
+}
+
+/// The NS targets of a referral for `name` when all are under `name` and none has glue.
+fn glueless_targets(name: &Name, referral: &Message) -> Option<Vec<String>> {
+    let targets: Vec<&Name> = referral
+        .name_servers()
+        .iter()
+        .filter(|record| record.name() == name)
+        .filter_map(|record| match record.data() {
+            Some(RData::NS(ns)) => Some(&ns.0),
+            _ => None,
+        })
+        .collect();
+    let has_glue = |target: &Name| {
+        referral.additionals().iter().any(|record| {
+            record.name() == target && matches!(record.data(), Some(RData::A(_)) | Some(RData::AAAA(_)))
+        })
+    };
+    let in_bailiwick = targets.iter().all(|target| name.zone_of(target));
+    (!targets.is_empty() && in_bailiwick && !targets.iter().any(|target| has_glue(target)))
+        .then(|| targets.iter().map(|target| target.to_string()).collect())
+}
+
+/// True when the CAA set has issue or issuewild entries and every CA they authorize is approved.
+/// An `issue ";"` entry forbids all issuance (RFC 8659), so a set of only those is compliant.
+fn caa_compliant(records: &[CAA], approved: &[String]) -> bool {
+    let issuers: Vec<Option<String>> = records
+        .iter()
+        .filter(|caa| caa.tag().is_issue() || caa.tag().is_issuewild())
+        .map(|caa| match caa.value() {
+            CaaValue::Issuer(name, _) => {
+                name.as_ref().map(|name| name.to_string().trim_end_matches('.').to_ascii_lowercase())
+            }
+            _ => None,
+        })
+        .collect();
+    !issuers.is_empty() && issuers.iter().flatten().all(|issuer| approved.contains(issuer))
+}
+
+fn is_enum_domain(domain: &str) -> bool {
+    let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+    domain == "e164.arpa" || domain.ends_with(".e164.arpa")
+}
+
+/// Record type code of DNAME (RFC 6672).
+const DNAME: u16 = 39;
+
+/// Address records for a name, plus the CNAME targets the resolver followed to reach them.
+#[derive(Debug, Default)]
+struct AddressAnswers {
+    ips: Vec<String>,
+    cnames: Vec<String>,
+}
+
+impl AddressAnswers {
+    fn from_lookup(lookup: &Lookup) -> Self {
+        let mut answers = Self::default();
+        for record in lookup.records() {
+            match record.data() {
+                Some(RData::A(a)) => answers.ips.push(a.to_string()),
+                Some(RData::AAAA(aaaa)) => answers.ips.push(aaaa.to_string()),
+                Some(RData::CNAME(target)) => answers.cnames.push(target.to_string()),
+                _ => {}
+            }
+        }
+        answers
+    }
+}
+
+/// Subdomains probed by `wildcard-scope` when no --labels are given.
+const WILDCARD_PROBE_LABELS: &[&str] = &[
+    "www", "mail", "smtp", "imap", "pop", "webmail", "mx", "ns1", "ns2", "api", "app", "admin",
+    "portal", "blog", "shop", "store", "cdn", "static", "assets", "img", "m", "dev", "staging",
+    "test", "beta", "vpn", "remote", "ftp", "docs", "status", "support", "help",
+];
+
+/// What a wildcard zone answers for a sample of its subdomains.
+#[derive(Debug, Serialize)]
+struct WildcardScope {
+    domain: String,
+    wildcard: bool,
+    /// The answer for a random label, i.e. what the wildcard record resolves to
+    wildcard_answer: Vec<String>,
+    labels: Vec<WildcardLabel>,
+}
+
+#[derive(Debug, Serialize)]
+struct WildcardLabel {
+    name: String,
+    answer: LabelAnswer,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    records: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum LabelAnswer {
+    /// Same answer as a random label: caught by the wildcard
+    Wildcard,
+    /// An answer of its own: explicitly configured
+    Distinct,
+    /// No address records at all
+    Absent,
+    Error,
+}
+
+/// CNAME targets then addresses, sorted so answers can be compared regardless of record order.
+fn answer_fingerprint(answers: &AddressAnswers) -> Vec<String> {
+    let mut cnames = answers.cnames.clone();
+    let mut ips = answers.ips.clone();
+    cnames.sort();
+    ips.sort();
+    cnames.into_iter().chain(ips).collect()
+}
+
+/// Address ranges announced by the major CDNs.
+const CDN_PREFIXES: &[(&str, &str)] = &[
+    ("Cloudflare", "103.21.244.0/22"),
+    ("Cloudflare", "103.22.200.0/22"),
+    ("Cloudflare", "103.31.4.0/22"),
+    ("Cloudflare", "104.16.0.0/13"),
+    ("Cloudflare", "104.24.0.0/14"),
+    ("Cloudflare", "108.162.192.0/18"),
+    ("Cloudflare", "131.0.72.0/22"),
+    ("Cloudflare", "141.101.64.0/18"),
+    ("Cloudflare", "162.158.0.0/15"),
+    ("Cloudflare", "172.64.0.0/13"),
+    ("Cloudflare", "173.245.48.0/20"),
+    ("Cloudflare", "188.114.96.0/20"),
+    ("Cloudflare", "190.93.240.0/20"),
+    ("Cloudflare", "197.234.240.0/22"),
+    ("Cloudflare", "198.41.128.0/17"),
+    ("Cloudflare", "2400:cb00::/32"),
+    ("Cloudflare", "2606:4700::/32"),
+    ("Cloudflare", "2803:f800::/32"),
+    ("Cloudflare", "2405:b500::/32"),
+    ("Cloudflare", "2405:8100::/32"),
+    ("Cloudflare", "2a06:98c0::/29"),
+    ("Cloudflare", "2c0f:f248::/32"),
+    ("Fastly", "23.235.32.0/20"),
+    ("Fastly", "43.249.72.0/22"),
+    ("Fastly", "103.244.50.0/24"),
+    ("Fastly", "103.245.222.0/23"),
+    ("Fastly", "103.245.224.0/24"),
+    ("Fastly", "104.156.80.0/20"),
+    ("Fastly", "140.248.64.0/18"),
+    ("Fastly", "140.248.128.0/17"),
+    ("Fastly", "146.75.0.0/17"),
+    ("Fastly", "151.101.0.0/16"),
+    ("Fastly", "157.52.64.0/18"),
+    ("Fastly", "167.82.0.0/17"),
+    ("Fastly", "172.111.64.0/18"),
+    ("Fastly", "185.31.16.0/22"),
+    ("Fastly", "199.27.72.0/21"),
+    ("Fastly", "199.232.0.0/16"),
+    ("Fastly", "2a04:4e40::/32"),
+    ("Fastly", "2a04:4e42::/32"),
+    ("Akamai", "2.16.0.0/13"),
+    ("Akamai", "23.0.0.0/12"),
+    ("Akamai", "23.192.0.0/11"),
+    ("Akamai", "95.100.0.0/15"),
+    ("Akamai", "96.16.0.0/15"),
+    ("Akamai", "104.64.0.0/10"),
+    ("Akamai", "184.24.0.0/13"),
+    ("Akamai", "184.50.0.0/15"),
+    ("Akamai", "2600:1400::/24"),
+    ("Akamai", "2a02:26f0::/29"),
+    ("CloudFront", "13.32.0.0/15"),
+    ("CloudFront", "13.224.0.0/14"),
+    ("CloudFront", "18.64.0.0/14"),
+    ("CloudFront", "52.84.0.0/15"),
+    ("CloudFront", "54.182.0.0/16"),
+    ("CloudFront", "54.192.0.0/16"),
+    ("CloudFront", "54.230.0.0/16"),
+    ("CloudFront", "54.239.128.0/18"),
+    ("CloudFront", "99.84.0.0/16"),
+    ("CloudFront", "99.86.0.0/16"),
+    ("CloudFront", "143.204.0.0/16"),
+    ("CloudFront", "205.251.192.0/19"),
+    ("CloudFront", "2600:9000::/28"),
+];
+
+/// CNAME targets that hand a name over to a CDN edge.
+const CDN_CNAME_SUFFIXES: &[(&str, &str)] = &[
+    ("Cloudflare", "cdn.cloudflare.net"),
+    ("Fastly", "fastly.net"),
+    ("Fastly", "fastlylb.net"),
+    ("Akamai", "akamai.net"),
+    ("Akamai", "akamaiedge.net"),
+    ("Akamai", "akamaihd.net"),
+    ("Akamai", "edgekey.net"),
+    ("Akamai", "edgesuite.net"),
+    ("CloudFront", "cloudfront.net"),
+];
+
+fn cdn_prefixes() -> &'static [(&'static str, IpNet)] {
+    static PREFIXES: OnceLock<Vec<(&'static str, IpNet)>> = OnceLock::new();
+    PREFIXES.get_or_init(|| {
+        CDN_PREFIXES
+            .iter()
+            .map(|(cdn, prefix)| (*cdn, prefix.parse().expect("valid builtin CDN prefix")))
+            .collect()
+    })
+}
+
+/// Names the CDN fronting a domain, preferring CNAME targets over address ranges.
+fn detect_cdn(cnames: &[String], ips: &[String]) -> Option<&'static str> {
+    let by_cname = cnames.iter().find_map(|cname| {
+        let cname = cname.trim_end_matches('.').to_ascii_lowercase();
+        CDN_CNAME_SUFFIXES
+            .iter()
+            .find(|(_, suffix)| cname == *suffix || cname.ends_with(&format!(".{}", suffix)))
+            .map(|(cdn, _)| *cdn)
+    });
+    by_cname.or_else(|| {
+        ips.iter().filter_map(|ip| ip.parse::<IpAddr>().ok()).find_map(|ip| {
+            cdn_prefixes()
+                .iter()
+                .find(|(_, prefix)| prefix.contains(&ip))
+                .map(|(cdn, _)| *cdn)
+        })
+    })
+}
+
+/// Concurrency caps for TLDs whose registries are known to throttle lookups aggressively.
+/// Overridden per TLD with `--tld-concurrency`.
+const DEFAULT_TLD_CONCURRENCY: &[(&str, usize)] = &[
+    ("cn", 2),
+    ("de", 4),
+    ("it", 4),
+    ("jp", 4),
+    ("nl", 4),
+    ("pl", 4),
+    ("ru", 4),
+];
+
+/// Merges user overrides into the builtin TLD caps. A limit of 0 removes the cap.
+fn effective_tld_concurrency(overrides: &[(String, usize)]) -> Vec<(String, usize)> {
+    let mut limits: Vec<(String, usize)> = DEFAULT_TLD_CONCURRENCY
+        .iter()
+        .map(|&(tld, limit)| (tld.to_string(), limit))
+        .collect();
+
+    for (tld, limit) in overrides {
+        limits.retain(|(existing, _)| existing != tld);
+        limits.push((tld.clone(), *limit));
+    }
+
+    limits.retain(|&(_, limit)| limit > 0);
+    limits.sort();
+    limits
+}
+
+/// A lowercase label that no zone plausibly has a record for.
+fn random_label() -> String {
+    rand::thread_rng()
+        .sample_iter(&rand::distributions::Alphanumeric)
+        .take(20)
+        .map(|byte| char::from(byte).to_ascii_lowercase())
+        .collect()
+}
+
+fn tld_of(domain: &str) -> String {
+    let name = domain.strip_suffix('.').unwrap_or(domain);
+    name.rsplit('.').next().unwrap_or(name).to_ascii_lowercase()
+}
+
+fn parse_tld_concurrency(value: &str) -> Result<(String, usize), String> {
+    let (tld, limit) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected TLD=N, got '{}'", value))?;
+    let limit = limit
+        .parse::<usize>()
+        .map_err(|_| format!("invalid concurrency limit '{}'", limit))?;
+    Ok((tld.trim_start_matches('.').to_ascii_lowercase(), limit))
+}
+
+#[derive(Clone)]
+struct TlsProber {
+    http: reqwest::Client,
+    https: reqwest::Client,
+}
+
+impl TlsProber {
+    fn new(connect_timeout: Duration) -> Result<Self, String> {
+        let builder = || {
+            reqwest::Client::builder()
+                .redirect(reqwest::redirect::Policy::none())
+                .connect_timeout(connect_timeout)
+                .timeout(Duration::from_secs(5))
+        };
+        let http = builder().build().map_err(|e| e.to_string())?;
+        // Invalid certificates are accepted so that expired ones still report their notAfter.
+        let https = builder()
+            .tls_info(true)
+            .danger_accept_invalid_certs(true)
+            .build()
+            .map_err(|e| e.to_string())?;
+        Ok(Self { http, https })
+    }
+
+    async fn probe(&self, status: &mut DomainStatus) {
+        let mut errors = Vec::new();
+
+        match self.http.get(format!("http://{}/", status.domain)).send().await {
+            Ok(response) => {
+                status.redirects_to_https = response.status().is_redirection()
+                    && response
+                        .headers()
+                        .get(reqwest::header::LOCATION)
+                        .and_then(|location| location.to_str().ok())
+                        .is_some_and(|location| location.starts_with("https://"));
+            }
+            Err(e) => errors.push(format!("HTTP error: {}", e)),
+        }
+
+        match self.https.get(format!("https://{}/", status.domain)).send().await {
+            Ok(response) => {
+                let der = response
+                    .extensions()
+                    .get::<reqwest::tls::TlsInfo>()
+                    .and_then(|info| info.peer_certificate());
+                match der.map(x509_parser::parse_x509_certificate) {
+                    Some(Ok((_, cert))) => {
+                        status.cert_expiry = chrono::DateTime::from_timestamp(cert.validity().not_after.timestamp(), 0)
+                            .map(|expiry| expiry.to_rfc3339());
+                    }
+                    Some(Err(e)) => errors.push(format!("TLS certificate parse error: {}", e)),
+                    None => errors.push("TLS error: no peer certificate".to_string()),
+                }
+            }
+            Err(e) => errors.push(format!("TLS error: {}", e)),
+        }
+
+        if !errors.is_empty() {
+            status.http_error = Some(errors.join("; "));
+        }
+    }
+}
+
+const RDAP_BOOTSTRAP_URL: &str = "https://data.iana.org/rdap/dns.json";
+
+/// Typical days from expiry until a domain is released: auto-renew grace, redemption and
+/// pending-delete combined. TLDs missing here get no drop estimate.
+const DROP_GRACE_DAYS: &[(&str, i64)] = &[
+    ("biz", 80),
+    ("com", 80),
+    ("info", 80),
+    ("net", 80),
+    ("org", 80),
+    ("be", 40),
+    ("eu", 40),
+    ("nl", 40),
+    ("uk", 92),
+];
+
+#[derive(Deserialize)]
+struct RdapBootstrap {
+    services: Vec<(Vec<String>, Vec<String>)>,
+}
+
+/// RDAP lookups routed to each TLD's registry through the IANA bootstrap file.
+#[derive(Clone)]
+struct RdapClient {
+    http: reqwest::Client,
+    servers: std::sync::Arc<HashMap<String, String>>,
+}
+
+impl RdapClient {
+    async fn new(connect_timeout: Duration) -> Result<Self, String> {
+        let http = reqwest::Client::builder()
+            .connect_timeout(connect_timeout)
+            .timeout(Duration::from_secs(10))
+            .build()
+            .map_err(|e| e.to_string())?;
+        let bootstrap: RdapBootstrap = http
+            .get(RDAP_BOOTSTRAP_URL)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| format!("failed to fetch RDAP bootstrap: {}", e))?
+            .json()
+            .await
+            .map_err(|e| format!("invalid RDAP bootstrap: {}", e))?;
+
+        let mut servers = HashMap::new();
+        for (tlds, urls) in bootstrap.services {
+            let Some(url) = urls.iter().find(|url| url.starts_with("https://")).or(urls.first()) else {
+                continue;
+            };
+            for tld in tlds {
+                servers.insert(tld.to_ascii_lowercase(), url.trim_end_matches('/').to_string());
+            }
+        }
+        Ok(Self { http, servers: std::sync::Arc::new(servers) })
+    }
+
+    async fn lookup(&self, status: &mut DomainStatus) {
+        let Some(server) = self.servers.get(&tld_of(&status.domain)) else {
+            status.rdap_error = Some("no RDAP server for this TLD".to_string());
+            return;
+        };
+        let response = self
+            .http
+            .get(format!("{}/domain/{}", server, status.domain))
+            .header(reqwest::header::ACCEPT, "application/rdap+json")
+            .send()
+            .await;
+        if matches!(&response, Ok(response) if response.status() == reqwest::StatusCode::NOT_FOUND) {
+            status.rdap_registered = Some(false);
+            if status.registered {
+                status.rdap_error = Some("RDAP server has no record of the domain".to_string());
+            }
+            return;
+        }
+        let response = response.and_then(|response| response.error_for_status());
+        let body: serde_json::Value = match response {
+            Ok(response) => match response.json().await {
+                Ok(body) => body,
+                Err(e) => {
+                    status.rdap_error = Some(format!("RDAP response error: {}", e));
+                    return;
+                }
+            },
+            Err(e) => {
+                status.rdap_error = Some(format!("RDAP error: {}", e));
+                return;
+            }
+        };
+
+        status.rdap_registered = Some(true);
+        status.registrar = rdap_registrar(&body);
+
+        let expiry = body["events"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .find(|event| event["eventAction"] == "expiration")
+            .and_then(|event| event["eventDate"].as_str())
+            .and_then(|date| chrono::DateTime::parse_from_rfc3339(date).ok())
+            .map(|date| date.with_timezone(&Utc));
+        status.expires_at = expiry.map(|expiry| expiry.to_rfc3339());
+        status.drop_eta = expiry.and_then(|expiry| drop_eta(&status.domain, expiry)).map(|eta| eta.to_rfc3339());
+    }
+}
+
+/// The display name (vCard `fn`) of the entity holding the registrar role.
+fn rdap_registrar(body: &serde_json::Value) -> Option<String> {
+    let registrar = body["entities"].as_array()?.iter().find(|entity| {
+        entity["roles"]
+            .as_array()
+            .is_some_and(|roles| roles.iter().any(|role| role == "registrar"))
+    })?;
+    registrar["vcardArray"][1]
+        .as_array()?
+        .iter()
+        .find(|property| property[0] == "fn")
+        .and_then(|property| property[3].as_str())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+}
+
+/// Takes the verdict from the first source in `precedence` that gave a definite one, warning about
+/// any other source that disagreed. With no definite answer from a listed source, DNS's stands.
+fn apply_source_precedence(status: &mut DomainStatus, precedence: &[VerdictSource]) {
+    let Some((winner, registered)) =
+        precedence.iter().find_map(|source| source.verdict(status).map(|registered| (*source, registered)))
+    else {
+        status.verdict_source = VerdictSource::Dns.name().to_string();
+        return;
+    };
+    let describe = |registered: bool| if registered { "registered" } else { "available" };
+    for source in [VerdictSource::Dns, VerdictSource::Rdap] {
+        match source.verdict(status) {
+            Some(other) if source != winner && other != registered => status.warnings.push(format!(
+                "{} says {} but {} says {}; using {}",
+                source.name(),
+                describe(other),
+                winner.name(),
+                describe(registered),
+                winner.name()
+            )),
+            _ => {}
+        }
+    }
+    status.registered = registered;
+    status.unknown = false;
+    status.verdict_source = winner.name().to_string();
+}
+
+/// Estimates when an expired domain becomes registrable again from its TLD's typical grace period.
+fn drop_eta(domain: &str, expiry: chrono::DateTime<Utc>) -> Option<chrono::DateTime<Utc>> {
+    if expiry > Utc::now() {
+        return None;
+    }
+    let tld = tld_of(domain);
+    let (_, days) = DROP_GRACE_DAYS.iter().find(|(grace_tld, _)| *grace_tld == tld)?;
+    Some(expiry + chrono::Duration::days(*days))
+}
+
+/// Builds the resolver configuration for the given nameservers (the preset if none), probing
+/// each one first. Also returns the addresses it ended up using.
+async fn build_resolver_config(
+    nameservers: Vec<SocketAddr>,
+    preset: ResolverPreset,
+    skip_unreachable: bool,
+    opts: ResolverOpts,
+    connect_timeout: Duration,
+) -> Result<(ResolverConfig, Vec<SocketAddr>), String> {
+    if nameservers.is_empty() {
+        let config = preset.config()?;
+        let mut servers: Vec<SocketAddr> = config.name_servers().iter().map(|ns| ns.socket_addr).collect();
+        servers.dedup();
+        return Ok((config, servers));
+    }
+
+    // A single short attempt: the probe only needs to tell a down server from a live one
+    let mut probe_opts = opts;
+    probe_opts.timeout = connect_timeout;
+    probe_opts.attempts = 1;
+    let probes =
+        futures::future::join_all(nameservers.iter().map(|&addr| probe_nameserver(addr, probe_opts))).await;
+    let (usable, unreachable): (Vec<_>, Vec<_>) = nameservers
+        .into_iter()
+        .zip(probes)
+        .partition(|(_, reachable)| *reachable);
+
+    if !unreachable.is_empty() {
+        let dropped: Vec<String> = unreachable.iter().map(|(addr, _)| addr.to_string()).collect();
+        if !skip_unreachable {
+            return Err(format!(
+                "Nameserver(s) unreachable: {} (use --skip-unreachable-resolvers to continue without them)",
+                dropped.join(", ")
+            ));
+        }
+        if usable.is_empty() {
+            return Err(format!("No usable nameservers; all failed the startup probe: {}", dropped.join(", ")));
+        }
+        eprintln!("Warning: dropping unreachable nameserver(s): {}", dropped.join(", "));
+    }
+
+    let usable: Vec<SocketAddr> = usable.into_iter().map(|(addr, _)| addr).collect();
+    Ok((resolver_config(&usable), usable))
+}
+
+impl DomainChecker {
+    async fn new(
+        nameservers: Vec<SocketAddr>,
+        skip_unreachable: bool,
+        options: CheckOptions,
+    ) -> Result<Self, String> {
+        let tls_prober = if options.tls_check {
+            Some(TlsProber::new(options.timeouts.connect)?)
+        } else {
+            None
+        };
+        let rdap = if options.rdap {
+            Some(RdapClient::new(options.timeouts.connect).await?)
+        } else {
+            None
+        };
+        let tld_limits = effective_tld_concurrency(&options.tld_concurrency).into_iter().collect();
+        let zone = match &options.zone_file {
+            Some(path) => Some(Zone::load(path, options.zone_origin.as_deref())?),
+            None => None,
+        };
+        let adaptive = options.adaptive.map(|(min, max)| AdaptiveLimit::new(min, max));
+        let latency_throttle = options.target_latency.map(LatencyThrottle::new);
+        let opts = resolver_opts(&options);
+        let (config, servers) = build_resolver_config(nameservers, options.resolver, skip_unreachable, opts, options.timeouts.connect).await?;
+        let resolver = TokioAsyncResolver::tokio(config.clone(), opts);
+
+        // The fallback only re-runs the lookups, so it shares everything but the resolver
+        let fallback = match options.fallback_resolver {
+            Some(fallback) => {
+                let config = fallback.config()?;
+                let mut servers: Vec<SocketAddr> =
+                    config.name_servers().iter().map(|ns| ns.socket_addr).collect();
+                servers.dedup();
+                Some(Box::new(Self {
+                    resolver: TokioAsyncResolver::tokio(config.clone(), opts),
+                    config,
+                    opts,
+                    servers,
+                    // The fallback is a public resolver, which wouldn't know the TSIG key
+                    options: CheckOptions { fallback_resolver: None, tsig: None, ..options.clone() },
+                    tls_prober: tls_prober.clone(),
+                    rdap: rdap.clone(),
+                    zone: None,
+                    adaptive: None,
+                    latency_throttle: None,
+                    tld_limits: HashMap::new(),
+                    fallback: None,
+                    confirmer: None,
+                    voters: Vec::new(),
+                    replay: None,
+                }))
+            }
+            None => None,
+        };
+
+        let confirmer = match options.confirm_resolver {
+            Some(confirm) => {
+                let config = confirm.config()?;
+                let mut servers: Vec<SocketAddr> =
+                    config.name_servers().iter().map(|ns| ns.socket_addr).collect();
+                servers.dedup();
+                Some(Box::new(Self::verdict_only(config, servers, opts, &options)))
+            }
+            None => None,
+        };
+
+        // Voters only need a verdict, so they skip every optional lookup
+        let voters = match options.consensus {
+            Some(quorum) if quorum > servers.len() => {
+                return Err(format!(
+                    "--consensus {} needs at least {} nameservers, but only {} are configured",
+                    quorum,
+                    quorum,
+                    servers.len()
+                ));
+            }
+            Some(_) => servers
+                .iter()
+                .map(|&addr| {
+                    let mut voter = Self::verdict_only(resolver_config(&[addr]), vec![addr], opts, &options);
+                    voter.options.tsig = options.tsig.clone();
+                    voter
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+
+        Ok(Self {
+            resolver,
+            config,
+            opts,
+            servers,
+            options,
+            tls_prober,
+            rdap,
+            zone,
+            adaptive,
+            latency_throttle,
+            tld_limits,
+            fallback,
+            confirmer,
+            voters,
+            replay: None,
+        })
+    }
+
+    /// A checker for a second opinion on the verdict alone, skipping every optional lookup.
+    fn verdict_only(config: ResolverConfig, servers: Vec<SocketAddr>, opts: ResolverOpts, options: &CheckOptions) -> Self {
+        Self {
+            resolver: TokioAsyncResolver::tokio(config.clone(), opts),
+            config,
+            opts,
+            servers,
+            options: CheckOptions {
+                resolver: options.resolver,
+                timeouts: options.timeouts,
+                registered_requires: options.registered_requires,
+                ip_version: options.ip_version,
+                recursion_desired: options.recursion_desired,
+                fresh_ports: options.fresh_ports,
+                ..CheckOptions::default()
+            },
+            tls_prober: None,
+            rdap: None,
+            zone: None,
+            adaptive: None,
+            latency_throttle: None,
+            tld_limits: HashMap::new(),
+            fallback: None,
+            confirmer: None,
+            voters: Vec::new(),
+            replay: None,
+        }
+    }
+
+    /// The resolver to use for one lookup. With `--fresh-ports` that's a brand-new resolver, so no
+    /// pooled connection, socket or cache entry carries over from earlier queries.
+    fn resolver(&self) -> TokioAsyncResolver {
+        if self.options.fresh_ports {
+            TokioAsyncResolver::tokio(self.config.clone(), self.opts)
+        } else {
+            self.resolver.clone()
+        }
+    }
+
+    /// True once the resolver answers at all. The name is random so a cached answer can't mask an
+    /// outage; NXDOMAIN proves the resolver is reachable just as well as a record would.
+    async fn resolver_reachable(&self) -> bool {
+        match self.resolver().lookup(format!("{}.", random_label()), RecordType::A).await {
+            Ok(_) => true,
+            Err(e) => matches!(e.kind(), ResolveErrorKind::NoRecordsFound { .. }),
+        }
+    }
+
+    /// Compares each label's address answer with a random label's. A random label can only be
+    /// answered by a wildcard, so labels with the same answer are most likely caught by it too.
+    /// Wildcards that rotate addresses per query make every label look distinct.
+    async fn wildcard_scope(&self, domain: &str, labels: &[String]) -> WildcardScope {
+        let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+        let probe = self.lookup_addresses(&format!("{}.{}", random_label(), domain)).await;
+        let wildcard_answer = probe.as_ref().map(answer_fingerprint).unwrap_or_default();
+        let labels = futures::future::join_all(labels.iter().map(|label| {
+            let name = format!("{}.{}", label.trim_matches('.').to_ascii_lowercase(), domain);
+            let wildcard_answer = &wildcard_answer;
+            async move {
+                let (answer, records, error) = match self.lookup_addresses(&name).await {
+                    Ok(answers) => {
+                        let records = answer_fingerprint(&answers);
+                        let answer = if records.is_empty() {
+                            LabelAnswer::Absent
+                        } else if records == *wildcard_answer {
+                            LabelAnswer::Wildcard
+                        } else {
+                            LabelAnswer::Distinct
+                        };
+                        (answer, records, None)
+                    }
+                    Err(e) if matches!(e.kind(), ResolveErrorKind::NoRecordsFound { .. }) => {
+                        (LabelAnswer::Absent, Vec::new(), None)
+                    }
+                    Err(e) => (LabelAnswer::Error, Vec::new(), Some(e.to_string())),
+                };
+                WildcardLabel { name, answer, records, error }
+            }
+        }))
+        .await;
+
+        WildcardScope { wildcard: !wildcard_answer.is_empty(), domain, wildcard_answer, labels }
+    }
+
+    /// Probe the resolver until it answers or `max_wait` runs out.
+    async fn wait_for_resolver(&self, max_wait: Duration) -> bool {
+        let deadline = Instant::now() + max_wait;
+        loop {
+            if self.resolver_reachable().await {
+                return true;
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return false;
+            }
+            tokio::time::sleep(remaining.min(RECOVER_PROBE_INTERVAL)).await;
+        }
+    }
+
+    /// Serves every later check from recorded results. Domains missing from the recording get
+    /// an error rather than a live lookup.
+    fn replay_from(&mut self, statuses: Vec<DomainStatus>) {
+        self.replay = Some(statuses.into_iter().map(|status| (status.domain.clone(), status)).collect());
+    }
+
+    fn replayed(&self, domain: &str) -> Option<DomainStatus> {
+        let replay = self.replay.as_ref()?;
+        Some(replay.get(domain).cloned().unwrap_or_else(|| {
+            let mut status = DomainStatus::new(domain.to_string());
+            status.error = Some("Not in replay file".to_string());
+            status.error_kind = Some("not_recorded".to_string());
+            status
+        }))
+    }
+
+    /// Runs the full check, cut short by --domain-deadline. Dropping the check's future cancels
+    /// every query still in flight for the domain, freeing its concurrency slot.
+    async fn check_domain(&self, domain: String) -> DomainStatus {
+        let mut status = match self.options.domain_deadline {
+            Some(deadline) => tokio::time::timeout(deadline, self.check_domain_unbounded(domain.clone()))
+                .await
+                .unwrap_or_else(|_| DomainStatus::deadline_exceeded(domain, deadline)),
+            None => self.check_domain_unbounded(domain).await,
+        };
+        status.mark_checked();
+        status
+    }
+
+    async fn check_domain_unbounded(&self, domain: String) -> DomainStatus {
+        if let Some(status) = self.replayed(&domain) {
+            return status;
+        }
+        let status = self.check_domain_once(domain).await;
+
+        let status = match self.options.recheck_nxdomain {
+            Some(delay) if status.nxdomain && !status.registered => {
+                tokio::time::sleep(delay).await;
+                self.check_domain_once(status.domain).await
+            }
+            _ => status,
+        };
+
+        let mut status = match &self.fallback {
+            Some(fallback) if status.inconclusive() => {
+                let mut status = fallback.check_domain_once(status.domain).await;
+                status.answered_by = Some("fallback".to_string());
+                status
+            }
+            Some(_) => DomainStatus { answered_by: Some("primary".to_string()), ..status },
+            None => status,
+        };
+
+        // A delegation that can never resolve looks like a plain timeout or SERVFAIL from the
+        // resolver, so ask the parent directly what it delegates to
+        if status.inconclusive() && self.zone.is_none() {
+            if let Some(nameservers) = self.glueless_loop(&status.domain).await {
+                status.error = Some(format!(
+                    "Glueless delegation loop: nameservers {} are inside the domain but the parent has no glue for them",
+                    nameservers.join(", ")
+                ));
+                status.error_kind = Some("glueless_loop".to_string());
+                status.unknown = false;
+                status.has_dns = true;
+                status.nameservers = nameservers;
+                status.registered = self.options.registered_requires.registered(status.has_dns, status.has_ip);
+            }
+        }
+
+        if let Some(quorum) = self.options.consensus {
+            self.apply_consensus(&mut status, quorum).await;
+        }
+
+        if self.options.decision {
+            status.decision = Some(self.decision(&status).await);
+        }
+
+        // A poisoned or stale negative answer is the costly mistake, so an NXDOMAIN only stands
+        // if the second resolver returns one too
+        if let Some(confirmer) = &self.confirmer {
+            if status.nxdomain && !status.registered {
+                let second = confirmer.check_domain_once(status.domain.clone()).await;
+                let confirmed = second.nxdomain && !second.registered;
+                status.nxdomain_confirmed = Some(confirmed);
+                if !confirmed {
+                    status.uncertain = true;
+                    status.unknown = true;
+                }
+            }
+        }
+
+        if !self.options.source_precedence.is_empty() {
+            apply_source_precedence(&mut status, &self.options.source_precedence);
+        }
+
+        status
+    }
+
+    async fn decision(&self, status: &DomainStatus) -> Decision {
+        let ips: Vec<IpAddr> = status.ip_addresses.iter().filter_map(|ip| ip.parse().ok()).collect();
+        let wildcard = match status.domain.trim_end_matches('.').split_once('.') {
+            Some((_, parent)) if status.has_ip => {
+                let answers = AddressAnswers { ips: status.ip_addresses.clone(), cnames: status.cnames.clone() };
+                match self.lookup_addresses(&format!("{}.{}", random_label(), parent)).await {
+                    Ok(sibling) => answer_fingerprint(&sibling) == answer_fingerprint(&answers),
+                    Err(_) => false,
+                }
+            }
+            _ => false,
+        };
+        Decision {
+            ns_present: status.has_dns,
+            a_present: ips.iter().any(IpAddr::is_ipv4),
+            aaaa_present: ips.iter().any(IpAddr::is_ipv6),
+            nxdomain: status.nxdomain,
+            wildcard,
+        }
+    }
+
+    /// Replaces the verdict with the majority of the nameservers' individual verdicts. Nameservers
+    /// that SERVFAIL or REFUSE don't vote but still count toward the confidence denominator; ones
+    /// that couldn't be reached at all (a timeout, or no route to an IPv6 server) are left out of it.
+    async fn apply_consensus(&self, status: &mut DomainStatus, quorum: usize) {
+        let votes = futures::future::join_all(
+            self.voters.iter().map(|voter| voter.check_domain_once(status.domain.clone())),
+        )
+        .await;
+        let (mut registered, mut unregistered) = (0, 0);
+        for vote in &votes {
+            if vote.registered {
+                registered += 1;
+            } else if !vote.inconclusive() && vote.error.is_none() {
+                unregistered += 1;
+            }
+        }
+        let agreeing = registered.max(unregistered);
+        if registered != unregistered {
+            status.registered = registered > unregistered;
+        }
+        let reached = votes.iter().filter(|vote| vote.registered || vote.error.is_none()).count();
+        status.consensus_confidence = Some(if reached == 0 { 0.0 } else { agreeing as f64 / reached as f64 });
+        status.uncertain = registered == unregistered || agreeing < quorum;
+    }
+
+    /// Asks the parent zone's servers for the domain's referral and returns its nameservers if
+    /// every one of them is in-bailiwick and none comes with glue, so resolving them needs the
+    /// domain itself.
+    async fn glueless_loop(&self, domain: &str) -> Option<Vec<String>> {
+        let name = Name::from_utf8(domain).ok()?;
+        let parent_ns = self.resolver().ns_lookup(name.base_name()).await.ok()?;
+        for ns in parent_ns.iter().take(3) {
+            let ips = self.lookup_addresses(&ns.to_string()).await.map(|answers| answers.ips).unwrap_or_default();
+            for ip in ips.iter().filter_map(|ip| ip.parse::<IpAddr>().ok()) {
+                let query = Query::query(name.clone(), RecordType::NS);
+                if let Ok(referral) = raw_query(SocketAddr::new(ip, 53), query, false, self.options.timeouts, None).await {
+                    return glueless_targets(&name, &referral);
+                }
+            }
+        }
+        None
+    }
+
+    async fn check_domain_once(&self, domain: String) -> DomainStatus {
+        let started = Instant::now();
+        let mut status = self.check_ns(domain).await;
+
+        // Check A/AAAA records
+        match self.lookup_addresses(&status.domain).await {
+            Ok(answers) if answers.ips.is_empty() => status.cnames = answers.cnames,
+            Ok(answers) => {
+                status.has_ip = true;
+                status.registered = true;
+                status.unknown = false;
+                status.ip_addresses = answers.ips;
+                status.cnames = answers.cnames;
+            }
+            Err(e) => {
+                if !status.registered {
+                    match e.kind() {
+                        ResolveErrorKind::NoRecordsFound { response_code, .. } => {
+                            status.servfail |= *response_code == ResponseCode::ServFail;
+                            status.refused |= *response_code == ResponseCode::Refused;
+                        }
+                        _ => self.record_error(&mut status, "IP lookup error", &e),
+                    }
+                }
+            }
+        }
+        status.response_time_ms = started.elapsed().as_millis() as u64;
+        status.registered = self.options.registered_requires.registered(status.has_dns, status.has_ip);
+
+        // ENUM numbers are provisioned through NAPTR records rather than NS or address records
+        if self.options.enum_all || is_enum_domain(&status.domain) {
+            status.naptr_records = match &self.zone {
+                Some(zone) => zone.rdata(&status.domain, RecordType::NAPTR),
+                None => match self.resolver().lookup(status.domain.as_str(), RecordType::NAPTR).await {
+                    Ok(lookup) => lookup
+                        .record_iter()
+                        .filter_map(|record| match record.data() {
+                            Some(RData::NAPTR(naptr)) => Some(naptr.to_string()),
+                            _ => None,
+                        })
+                        .collect(),
+                    Err(_) => Vec::new(),
+                },
+            };
+            status.registered |= !status.naptr_records.is_empty();
+        }
+
+        status.parked = status.nameservers.iter().any(|ns| is_parking_nameserver(ns));
+        status.cdn = detect_cdn(&status.cnames, &status.ip_addresses).map(str::to_string);
+        if status.parked && self.options.treat_parked_as_available {
+            status.registered = false;
+        }
+
+        if self.options.txt && status.registered {
+            status.txt_records = self.lookup_txt(&status.domain).await;
+        }
+
+        if self.options.email_policy && status.registered {
+            let txt = if self.options.txt { status.txt_records.clone() } else { self.lookup_txt(&status.domain).await };
+            status.spf = find_spf(&txt);
+            if let Some(spf) = &status.spf {
+                // One level only: the records an include points to aren't followed further
+                status.spf_includes = futures::future::join_all(spf_includes(spf).into_iter().map(|domain| async move {
+                    let spf = find_spf(&self.lookup_txt(&domain).await);
+                    SpfInclude { domain, spf }
+                }))
+                .await;
+            }
+            status.dmarc_policy = dmarc_policy(&self.lookup_txt(&format!("_dmarc.{}", status.domain)).await);
+        }
+
+        if self.options.dname && self.zone.is_none() {
+            status.dname = self.lookup_dname(&status.domain).await;
+        }
+
+        if !self.options.caa_issuers.is_empty() && status.registered {
+            let caa = self.lookup_caa(&status.domain).await;
+            status.caa_records = caa.iter().map(|record| record.to_string()).collect();
+            status.caa_compliant = Some(caa_compliant(&caa, &self.options.caa_issuers));
+        }
+
+        if self.options.mx && status.registered {
+            if let Some(zone) = &self.zone {
+                status.mx_records = zone
+                    .rdata(&status.domain, RecordType::MX)
+                    .iter()
+                    .filter_map(|mx| mx.split_whitespace().last().map(str::to_string))
+                    .collect();
+            } else if let Ok(mx) = self.resolver().mx_lookup(status.domain.clone()).await {
+                status.mx_records = mx.iter().map(|record| record.exchange().to_string()).collect();
+            }
+        }
+
+        if self.options.full_response {
+            status.full_response = Some(self.full_response(&status.domain).await);
+        }
+        if !self.options.recursion_desired && !self.offline() {
+            status.authoritative = match &status.full_response {
+                Some(full) if full.error.is_none() => Some(full.authoritative),
+                Some(_) => None,
+                None => self.raw_ns_query(&status.domain).await.ok().map(|message| message.authoritative()),
+            };
+        }
+
+        if self.options.resolve_all_ns && !status.nameservers.is_empty() {
+            status.nameserver_ips = self.resolve_nameservers(&status.nameservers).await;
+        }
+
+        if self.options.check_ns_reachable && !status.nameservers.is_empty() {
+            self.check_ns_reachability(&mut status).await;
+        }
+
+        if self.options.check_ns_consistency && !status.nameservers.is_empty() {
+            self.check_ns_consistency(&mut status).await;
+        }
+
+        if let Some((resolver, vantages)) = &self.options.vantages {
+            if status.registered {
+                self.check_vantages(&mut status, *resolver, vantages).await;
+            }
+        }
+
+        if let Some(rdap) = &self.rdap {
+            if status.registered || self.options.source_precedence.contains(&VerdictSource::Rdap) {
+                rdap.lookup(&mut status).await;
+            }
+        }
+
+        if let Some(prober) = &self.tls_prober {
+            if status.has_ip {
+                prober.probe(&mut status).await;
+            }
+        }
+
+        status
+    }
+
+    /// Records a failed lookup, tagging timeouts so they can be told apart from hard errors.
+    fn record_error(&self, status: &mut DomainStatus, context: &str, e: &ResolveError) {
+        status.error = Some(format!("{}: {}", context, e));
+        if is_timeout(e) {
+            status.error_kind = Some("timeout".to_string());
+            status.unknown = self.options.timeout_as_unknown;
+        }
+    }
+
+    /// NS lookup only, the cheap first step of every check.
+    async fn check_ns(&self, domain: String) -> DomainStatus {
+        let started = Instant::now();
+        let mut status = DomainStatus::new(domain);
+
+        if let Some(zone) = &self.zone {
+            status.nameservers = zone.rdata(&status.domain, RecordType::NS);
+            status.has_dns = !status.nameservers.is_empty();
+            status.registered = status.has_dns;
+            status.nxdomain = !zone.contains(&status.domain);
+            status.response_time_ms = started.elapsed().as_millis() as u64;
+            return status;
+        }
+
+        // Check NS records
+        let lookup = match &self.options.tsig {
+            Some(key) => self.signed_lookup(key, &status.domain, RecordType::NS).await,
+            None => self.resolver().ns_lookup(status.domain.clone()).await.map(|ns| ns.as_lookup().clone()),
+        };
+        match lookup {
+            Ok(ns_records) => {
+                status.has_dns = true;
+                status.registered = true;
+                status.nameservers = ns_records
+                    .iter()
+                    .filter_map(|record| match record {
+                        RData::NS(ns) => Some(ns.to_string()),
+                        _ => None,
+                    })
+                    .collect();
+            }
+            Err(e) => match e.kind() {
+                ResolveErrorKind::NoRecordsFound { response_code, .. } => {
+                    status.nxdomain = *response_code == ResponseCode::NXDomain;
+                    status.servfail = *response_code == ResponseCode::ServFail;
+                    status.refused = *response_code == ResponseCode::Refused;
+                }
+                _ => {
+                    if !status.registered {
+                        self.record_error(&mut status, "NS lookup error", &e);
+                    }
+                }
+            },
+        }
+        status.response_time_ms = started.elapsed().as_millis() as u64;
+
+        status
+    }
+
+    /// Whether checks are answered from --zone-file or --replay, so nothing may go out live.
+    fn offline(&self) -> bool {
+        self.zone.is_some() || self.replay.is_some()
+    }
+
+    /// The server raw queries go to: the first resolver, unless checks are answered offline.
+    fn raw_query_server(&self) -> RawQueryResult<SocketAddr> {
+        if self.offline() {
+            return Err("no live queries while answering from --zone-file or --replay".into());
+        }
+        self.servers.first().copied().ok_or_else(|| "no nameserver to query".into())
+    }
+
+    /// Sends the domain's NS query to the first resolver, bypassing the resolver's cache.
+    async fn raw_ns_query(&self, domain: &str) -> RawQueryResult<Message> {
+        let server = self.raw_query_server()?;
+        let query = Query::query(Name::from_utf8(domain)?, RecordType::NS);
+        raw_query(server, query, self.options.recursion_desired, self.options.timeouts, self.options.tsig.as_ref()).await
+    }
+
+    async fn full_response(&self, domain: &str) -> FullResponse {
+        let server = match self.raw_query_server() {
+            Ok(server) => server,
+            Err(e) => return FullResponse { error: Some(e.to_string()), ..FullResponse::default() },
+        };
+        match self.raw_ns_query(domain).await {
+            Ok(message) => FullResponse::from_message(server, &message),
+            Err(e) => FullResponse {
+                server: server.to_string(),
+                error: Some(e.to_string()),
+                ..FullResponse::default()
+            },
+        }
+    }
+
+    async fn lookup_addresses(&self, domain: &str) -> Result<AddressAnswers, ResolveError> {
+        if let Some(zone) = &self.zone {
+            let mut ips = Vec::new();
+            if self.options.ip_version != IpVersion::V6 {
+                ips.extend(zone.rdata(domain, RecordType::A));
+            }
+            if self.options.ip_version != IpVersion::V4 {
+                ips.extend(zone.rdata(domain, RecordType::AAAA));
+            }
+            let cnames = zone.rdata(domain, RecordType::CNAME);
+            return Ok(AddressAnswers { ips, cnames });
+        }
+
+        if let Some(key) = &self.options.tsig {
+            let types: &[RecordType] = match self.options.ip_version {
+                IpVersion::V4 => &[RecordType::A],
+                IpVersion::V6 => &[RecordType::AAAA],
+                IpVersion::Both => &[RecordType::A, RecordType::AAAA],
+            };
+            // Like the resolver's own dual-stack lookup, one family answering is enough
+            let mut answers = AddressAnswers::default();
+            let mut error = None;
+            for &record_type in types {
+                match self.signed_lookup(key, domain, record_type).await {
+                    Ok(lookup) => {
+                        let found = AddressAnswers::from_lookup(&lookup);
+                        answers.ips.extend(found.ips);
+                        if answers.cnames.is_empty() {
+                            answers.cnames = found.cnames;
+                        }
+                    }
+                    Err(e) => error = error.or(Some(e)),
+                }
+            }
+            return match error {
+                Some(e) if answers.ips.is_empty() && answers.cnames.is_empty() => Err(e),
+                _ => Ok(answers),
+            };
+        }
+
+        let resolver = self.resolver();
+        let lookup = match self.options.ip_version {
+            IpVersion::V4 => resolver.ipv4_lookup(domain).await?.as_lookup().clone(),
+            IpVersion::V6 => resolver.ipv6_lookup(domain).await?.as_lookup().clone(),
+            IpVersion::Both => resolver.lookup_ip(domain).await?.as_lookup().clone(),
+        };
+        Ok(AddressAnswers::from_lookup(&lookup))
+    }
+
+    /// One lookup as a TSIG-signed query to the first nameserver, since the stub resolver can't
+    /// sign. Failures come back as the resolver would report them.
+    async fn signed_lookup(&self, key: &TsigKey, name: &str, record_type: RecordType) -> Result<Lookup, ResolveError> {
+        let server = self.raw_query_server().map_err(|e| ResolveError::from(e.to_string()))?;
+        let query = Query::query(Name::from_utf8(name)?, record_type);
+        let message = raw_query(server, query.clone(), self.options.recursion_desired, self.options.timeouts, Some(key))
+            .await
+            .map_err(|e| match e.downcast_ref::<tokio::time::error::Elapsed>() {
+                Some(_) => ResolveError::from(ResolveErrorKind::Timeout),
+                None => ResolveError::from(format!("TSIG query failed: {}", e)),
+            })?;
+        let response = ResolveError::from_response(DnsResponse::new(message, Vec::new()), false)?;
+        Ok(Lookup::new_with_max_ttl(query, response.answers().to_vec().into()))
+    }
+
+    async fn resolve_nameservers(&self, nameservers: &[String]) -> Vec<NameserverIps> {
+        futures::future::join_all(nameservers.iter().map(|ns| async move {
+            let ips = self.lookup_addresses(ns).await.map(|answers| answers.ips).unwrap_or_default();
+            NameserverIps { ns: ns.clone(), ips }
+        }))
+        .await
+    }
+
+    /// TXT records of a name, each with its strings joined; empty if the lookup fails.
+    async fn lookup_txt(&self, name: &str) -> Vec<String> {
+        match &self.zone {
+            Some(zone) => zone.rdata(name, RecordType::TXT),
+            None => match self.resolver().txt_lookup(name).await {
+                Ok(txt) => txt.iter().map(|record| record.to_string()).collect(),
+                Err(_) => Vec::new(),
+            },
+        }
+    }
+
+    /// The CAA record set that applies to the domain: its own, or else the closest ancestor's
+    /// below the TLD (RFC 8659 section 3).
+    async fn lookup_caa(&self, domain: &str) -> Vec<CAA> {
+        let labels: Vec<&str> = domain.trim_end_matches('.').split('.').collect();
+        for start in 0..labels.len().saturating_sub(1) {
+            let name = labels[start..].join(".");
+            let found: Vec<CAA> = match &self.zone {
+                Some(zone) => zone
+                    .records(&name, RecordType::CAA)
+                    .into_iter()
+                    .filter_map(|data| match data {
+                        RData::CAA(caa) => Some(caa),
+                        _ => None,
+                    })
+                    .collect(),
+                None => match self.resolver().lookup(name.as_str(), RecordType::CAA).await {
+                    Ok(lookup) => lookup
+                        .record_iter()
+                        .filter_map(|record| match record.data() {
+                            Some(RData::CAA(caa)) => Some(caa.clone()),
+                            _ => None,
+                        })
+                        .collect(),
+                    Err(_) => Vec::new(),
+                },
+            };
+            if !found.is_empty() {
+                return found;
+            }
+        }
+        Vec::new()
+    }
+
+    /// The DNAME target at the domain itself, if any. The resolver has no DNAME type, so the
+    /// record comes back as unknown data holding the uncompressed target name.
+    async fn lookup_dname(&self, domain: &str) -> Option<String> {
+        let lookup = self.resolver().lookup(domain, RecordType::Unknown(DNAME)).await.ok()?;
+        lookup.records().iter().find_map(|record| match record.data() {
+            Some(RData::Unknown { code: DNAME, rdata }) => {
+                Name::read(&mut BinDecoder::new(rdata.anything())).ok().map(|target| target.to_string())
+            }
+            _ => None,
+        })
+    }
+
+    /// Splits the domain's nameservers by whether any of their addresses answers an
+    /// SOA query directly. A nameserver with no addresses counts as unreachable. Nothing is
+    /// probed when answering offline.
+    async fn check_ns_reachability(&self, status: &mut DomainStatus) {
+        if self.offline() {
+            return;
+        }
+        let Ok(name) = Name::from_utf8(&status.domain) else {
+            return;
+        };
+        let name = &name;
+        let reachable = futures::future::join_all(status.nameservers.iter().map(|ns| async move {
+            let ips = self.lookup_addresses(ns).await.map(|answers| answers.ips).unwrap_or_default();
+            let probes = ips.iter().filter_map(|ip| ip.parse::<IpAddr>().ok()).map(|ip| async move {
+                let query = Query::query(name.clone(), RecordType::SOA);
+                raw_query(SocketAddr::new(ip, 53), query, false, self.options.timeouts, None).await.is_ok()
+            });
+            futures::future::join_all(probes).await.into_iter().any(|answered| answered)
+        }))
+        .await;
+
+        for (ns, reachable) in status.nameservers.iter().zip(reachable) {
+            if reachable {
+                status.reachable_nameservers.push(ns.clone());
+            } else {
+                status.unreachable_nameservers.push(ns.clone());
+            }
+        }
+    }
+
+    /// Compares the A and NS records each nameserver serves for the domain without recursion.
+    /// Nameservers that don't answer are left out; that's what --check-ns-reachable reports.
+    /// Nothing is queried when answering offline.
+    async fn check_ns_consistency(&self, status: &mut DomainStatus) {
+        if self.offline() {
+            return;
+        }
+        let Ok(name) = Name::from_utf8(&status.domain) else {
+            return;
+        };
+        let name = &name;
+        let answers = futures::future::join_all(status.nameservers.iter().map(|ns| async move {
+            let ips = self.lookup_addresses(ns).await.map(|answers| answers.ips).unwrap_or_default();
+            for ip in ips.iter().filter_map(|ip| ip.parse::<IpAddr>().ok()) {
+                let server = SocketAddr::new(ip, 53);
+                let mut records = Vec::new();
+                let mut answered = true;
+                for record_type in [RecordType::A, RecordType::NS] {
+                    match raw_query(server, Query::query(name.clone(), record_type), false, self.options.timeouts, None).await {
+                        Ok(message) => records.extend(
+                            message
+                                .answers()
+                                .iter()
+                                .filter(|record| record.record_type() == record_type)
+                                .filter_map(|record| record.data().map(|data| format!("{} {}", record_type, data))),
+                        ),
+                        Err(_) => answered = false,
+                    }
+                }
+                if answered {
+                    records.sort();
+                    return Some((ns.clone(), records));
+                }
+            }
+            None
+        }))
+        .await;
+        let answers: Vec<(String, Vec<String>)> = answers.into_iter().flatten().collect();
+
+        if answers.is_empty() {
+            return;
+        }
+
+        let mut tally: HashMap<&Vec<String>, usize> = HashMap::new();
+        for (_, records) in &answers {
+            *tally.entry(records).or_default() += 1;
+        }
+        let mut counts: Vec<(&Vec<String>, usize)> = tally.into_iter().collect();
+        counts.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        // Without a strict majority there's no reference answer, so every nameserver diverges
+        let majority = match counts.as_slice() {
+            [(records, _)] => Some((*records).clone()),
+            [(records, first), (_, second), ..] if first > second => Some((*records).clone()),
+            _ => None,
+        };
+        status.divergent_nameservers = answers
+            .into_iter()
+            .filter(|(_, records)| Some(records) != majority.as_ref())
+            .map(|(ns, records)| DivergentNameserver { ns, records })
+            .collect();
+        status.ns_consistent = Some(status.divergent_nameservers.is_empty());
+    }
+
+    /// Resolves the domain's addresses once per --vantage, each query carrying that region's client
+    /// subnet so geo-routed names answer as they would for a client there.
+    async fn check_vantages(&self, status: &mut DomainStatus, resolver: SocketAddr, vantages: &[Vantage]) {
+        let Ok(name) = Name::from_utf8(&status.domain) else {
+            return;
+        };
+        let name = &name;
+        let record_types: &[RecordType] = match self.options.ip_version {
+            IpVersion::V4 => &[RecordType::A],
+            IpVersion::V6 => &[RecordType::AAAA],
+            IpVersion::Both => &[RecordType::A, RecordType::AAAA],
+        };
+        status.vantages = futures::future::join_all(vantages.iter().map(|&vantage| async move {
+            let mut ips = Vec::new();
+            let mut error = None;
+            for &record_type in record_types {
+                let mut request = query_message(Query::query(name.clone(), record_type), true);
+                if let Some(edns) = request.extensions_mut() {
+                    edns.options_mut().insert(EdnsOption::Subnet(vantage.client_subnet()));
+                }
+                match raw_exchange(resolver, request, self.options.timeouts, None).await {
+                    Ok(message) => ips.extend(message.answers().iter().filter_map(|record| match record.data() {
+                        Some(RData::A(a)) => Some(a.to_string()),
+                        Some(RData::AAAA(aaaa)) => Some(aaaa.to_string()),
+                        _ => None,
+                    })),
+                    Err(e) => error = Some(e.to_string()),
+                }
+            }
+            ips.sort();
+            ips.dedup();
+            VantageAnswer { region: vantage.name().to_string(), ips, error }
+        }))
+        .await;
+
+        // Regions whose queries failed have no answer to compare
+        let mut answered = status.vantages.iter().filter(|vantage| vantage.error.is_none()).map(|vantage| &vantage.ips);
+        if let Some(first) = answered.next() {
+            status.geo_variance = answered.any(|ips| ips != first);
+        }
+    }
+
+    /// Checks `domains` with up to `concurrent_limit` in flight, yielding results as they
+    /// complete. `progress`, if given, is called with (done, total) after each domain.
+    fn check_domains(
+        &self,
+        domains: Vec<String>,
+        concurrent_limit: usize,
+        progress: Option<ProgressCallback>,
+    ) -> impl Stream<Item = DomainStatus> + '_ {
+        let total = domains.len();
+        let mut done = 0;
+        self.tld_lanes(domains, concurrent_limit, move |domain| async move {
+            if let Some(throttle) = &self.latency_throttle {
+                throttle.wait().await;
+            }
+            let status = match &self.adaptive {
+                Some(adaptive) => {
+                    let permit = adaptive.acquire().await;
+                    let status = self.check_domain(domain).await;
+                    adaptive.release(permit, status.error.is_some());
+                    status
+                }
+                None => self.check_domain(domain).await,
+            };
+            if let Some(throttle) = &self.latency_throttle {
+                throttle.record(&status);
+            }
+            status
+        })
+        .inspect(move |_| {
+            done += 1;
+            if let Some(progress) = &progress {
+                progress(done, total);
+            }
+        })
+    }
+
+    /// Whether an NS sweep answer is final: only a definite NXDOMAIN is. A name without NS records
+    /// may still have addresses, and --recheck-nxdomain and --confirm-nxdomain want a second look.
+    /// --decision, --consensus and RDAP only run in the full check, so they keep every domain.
+    fn settled_by_sweep(&self, status: &DomainStatus) -> bool {
+        status.nxdomain
+            && !status.registered
+            && status.error.is_none()
+            && self.options.recheck_nxdomain.is_none()
+            && self.confirmer.is_none()
+            && !self.options.decision
+            && self.options.consensus.is_none()
+            && !self.options.rdap
+            && self.options.source_precedence.is_empty()
+    }
+
+    fn sweep_ns(
+        &self,
+        domains: Vec<String>,
+        concurrent_limit: usize,
+    ) -> impl Stream<Item = DomainStatus> + '_ {
+        self.tld_lanes(domains, concurrent_limit, move |domain| async move {
+            if let Some(status) = self.replayed(&domain) {
+                return status;
+            }
+            let mut status = self.check_ns(domain).await;
+            status.mark_checked();
+            status
+        })
+    }
+
+    /// Runs `check` over `domains` in lanes: one per capped TLD, sized to its cap, and one for the
+    /// rest. A saturated TLD only queues behind itself instead of filling slots other domains need.
+    /// A shared pool of `concurrent_limit` slots still bounds the checks in flight across lanes.
+    fn tld_lanes<'a, F, Fut>(
+        &'a self,
+        domains: Vec<String>,
+        concurrent_limit: usize,
+        check: F,
+    ) -> impl Stream<Item = DomainStatus> + 'a
+    where
+        F: Fn(String) -> Fut + Copy + 'a,
+        Fut: std::future::Future<Output = DomainStatus> + 'a,
+    {
+        let mut capped: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        let mut rest = Vec::new();
+        for domain in domains {
+            let tld = tld_of(&domain);
+            if self.tld_limits.contains_key(&tld) {
+                capped.entry(tld).or_default().push(domain);
+            } else {
+                rest.push(domain);
+            }
+        }
+
+        let slots = std::sync::Arc::new(Semaphore::new(concurrent_limit));
+        let lane = move |domains: Vec<String>, limit: usize| {
+            let slots = std::sync::Arc::clone(&slots);
+            stream::iter(domains)
+                .map(move |domain| {
+                    let slots = std::sync::Arc::clone(&slots);
+                    async move {
+                        let _slot = slots.acquire().await;
+                        check(domain).await
+                    }
+                })
+                .buffer_unordered(limit)
+                .boxed_local()
+        };
+        let mut lanes = vec![lane(rest, concurrent_limit)];
+        for (tld, domains) in capped {
+            lanes.push(lane(domains, self.tld_limits[&tld].min(concurrent_limit)));
+        }
+        stream::select_all(lanes)
+    }
+}
+
+/// How long a --serve client may take to send its request or accept a write before it's dropped.
+const SSE_CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Result frames kept for --serve clients that connect late; older results aren't replayed.
+const SSE_REPLAY_RESULTS: usize = 1000;
+
+/// What a late --serve client is sent before the live stream: the `started` frame, the most
+/// recent results, the latest `progress` and the `finished` frame, in that order.
+#[derive(Default)]
+struct SseReplay {
+    started: Option<String>,
+    results: std::collections::VecDeque<String>,
+    progress: Option<String>,
+    finished: Option<String>,
+}
+
+impl SseReplay {
+    fn record(&mut self, frame: String) {
+        match frame.strip_prefix("event: ").and_then(|rest| rest.split('\n').next()) {
+            Some("started") => self.started = Some(frame),
+            Some("progress") => self.progress = Some(frame),
+            Some("finished") => self.finished = Some(frame),
+            _ => {
+                if self.results.len() == SSE_REPLAY_RESULTS {
+                    self.results.pop_front();
+                }
+                self.results.push_back(frame);
+            }
+        }
+    }
+
+    fn frames(&self) -> String {
+        let mut frames = String::new();
+        frames.extend(self.started.as_deref());
+        frames.extend(self.results.iter().map(String::as_str));
+        frames.extend(self.progress.as_deref());
+        frames.extend(self.finished.as_deref());
+        frames
+    }
+}
+
+/// A minimal HTTP server for `--serve`: every request, whatever its path, gets the event stream.
+struct SseServer {
+    listener: tokio::net::TcpListener,
+}
+
+impl SseServer {
+    async fn bind(addr: SocketAddr) -> io::Result<Self> {
+        Ok(Self { listener: tokio::net::TcpListener::bind(addr).await? })
+    }
+
+    /// Spawns the server task, which sends every frame to all connected clients and replays
+    /// a bounded summary of earlier frames to new ones. The receiver fires when the first client is streaming.
+    /// Dropping the sender closes every stream and ends the task.
+    fn spawn(self, capacity: usize) -> (mpsc::Sender<String>, tokio::sync::oneshot::Receiver<()>, JoinHandle<()>) {
+        let (tx, mut rx) = mpsc::channel::<String>(capacity.max(1));
+        let (first_tx, first_rx) = tokio::sync::oneshot::channel();
+
+        let handle = tokio::spawn(async move {
+            let (ready_tx, mut ready_rx) = mpsc::channel::<TcpStream>(16);
+            let mut first_tx = Some(first_tx);
+            let mut replay = SseReplay::default();
+            let mut clients: Vec<TcpStream> = Vec::new();
+
+            loop {
+                tokio::select! {
+                    accepted = self.listener.accept() => match accepted {
+                        // The handshake runs apart from the loop so a slow client can't hold up the others
+                        Ok((stream, _)) => {
+                            let ready_tx = ready_tx.clone();
+                            tokio::spawn(async move {
+                                if let Ok(stream) = Self::handshake(stream).await {
+                                    let _ = ready_tx.send(stream).await;
+                                }
+                            });
+                        }
+                        Err(e) => eprintln!("Warning: --serve failed to accept a connection: {}", e),
+                    },
+                    Some(mut stream) = ready_rx.recv() => {
+                        if Self::write(&mut stream, &replay.frames()).await {
+                            clients.push(stream);
+                            if let Some(first_tx) = first_tx.take() {
+                                let _ = first_tx.send(());
+                            }
+                        }
+                    }
+                    received = rx.recv() => match received {
+                        Some(frame) => {
+                            let mut connected = Vec::with_capacity(clients.len());
+                            for mut stream in clients.drain(..) {
+                                if Self::write(&mut stream, &frame).await {
+                                    connected.push(stream);
+                                }
+                            }
+                            clients = connected;
+                            replay.record(frame);
+                        }
+                        None => break,
+                    },
+                }
+            }
+
+            for mut stream in clients {
+                let _ = stream.shutdown().await;
+            }
+        });
+
+        (tx, first_rx, handle)
+    }
+
+    /// Reads the request head and answers with the headers of an open-ended event stream.
+    async fn handshake(mut stream: TcpStream) -> io::Result<TcpStream> {
+        let mut request = Vec::new();
+        let mut buf = [0u8; 1024];
+        while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+            let read = tokio::time::timeout(SSE_CLIENT_TIMEOUT, stream.read(&mut buf))
+                .await
+                .map_err(|_| io::Error::from(io::ErrorKind::TimedOut))??;
+            if read == 0 || request.len() > 16 * 1024 {
+                return Err(io::Error::from(io::ErrorKind::InvalidData));
+            }
+            request.extend_from_slice(&buf[..read]);
+        }
+        stream
+            .write_all(
+                b"HTTP/1.1 200 OK\r\n\
+                  Content-Type: text/event-stream\r\n\
+                  Cache-Control: no-cache\r\n\
+                  Access-Control-Allow-Origin: *\r\n\
+                  Connection: close\r\n\r\n",
+            )
+            .await?;
+        Ok(stream)
+    }
+
+    /// False if the client is gone or too slow to keep up.
+    async fn write(stream: &mut TcpStream, data: &str) -> bool {
+        matches!(tokio::time::timeout(SSE_CLIENT_TIMEOUT, stream.write_all(data.as_bytes())).await, Ok(Ok(())))
+    }
+}
+
+/// How long one webhook POST may take before it counts as a failed attempt.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+struct Webhook {
+    client: reqwest::Client,
+    url: String,
+    batch_size: usize,
+    flush_interval: Duration,
+}
+
+impl Webhook {
+    fn new(url: String, batch_size: usize, flush_interval: Duration, connect_timeout: Duration) -> Result<Self, String> {
+        // A hung endpoint would otherwise stall the run, since a full queue blocks the checks
+        let client = reqwest::Client::builder()
+            .connect_timeout(connect_timeout)
+            .timeout(WEBHOOK_TIMEOUT)
+            .build()
+            .map_err(|e| e.to_string())?;
+        Ok(Self {
+            client,
+            url,
+            batch_size: batch_size.max(1),
+            flush_interval,
+        })
+    }
+
+    /// Spawns the batching task. Dropping the sender flushes what's left and ends the task.
+    /// At most `capacity` results wait in the queue; senders block beyond that.
+    fn spawn(self, capacity: usize) -> (mpsc::Sender<DomainStatus>, JoinHandle<()>) {
+        let (tx, mut rx) = mpsc::channel::<DomainStatus>(capacity.max(1));
+
+        let handle = tokio::spawn(async move {
+            let mut batch = Vec::with_capacity(self.batch_size);
+            let mut ticker = tokio::time::interval(self.flush_interval);
+
+            loop {
+                tokio::select! {
+                    received = rx.recv() => match received {
+                        Some(status) => {
+                            batch.push(status);
+                            if batch.len() >= self.batch_size {
+                                self.send(std::mem::take(&mut batch)).await;
+                            }
+                        }
+                        None => break,
+                    },
+                    _ = ticker.tick() => {
+                        if !batch.is_empty() {
+                            self.send(std::mem::take(&mut batch)).await;
+                        }
+                    }
+                }
+            }
+
+            if !batch.is_empty() {
+                self.send(batch).await;
+            }
+        });
+
+        (tx, handle)
+    }
+
+    async fn send(&self, batch: Vec<DomainStatus>) {
+        for attempt in 1..=2 {
+            let error = match self.client.post(&self.url).json(&batch).send().await {
+                Ok(response) if response.status().is_success() => return,
+                Ok(response) => format!("HTTP {}", response.status()),
+                Err(e) => e.to_string(),
+            };
+            eprintln!(
+                "Warning: webhook delivery of {} result(s) failed (attempt {}/2): {}",
+                batch.len(),
+                attempt,
+                error
+            );
+        }
+        eprintln!("Warning: dropping {} result(s) after webhook retry", batch.len());
+    }
+}
+
+fn create_check_result(domains: Vec<DomainStatus>, timestamp: String) -> CheckResult {
+    let summary = ResultSummary::from_statuses(&domains);
+
+    CheckResult {
+        timestamp,
+        check_count: summary.total_checked,
+        config: RunConfig::default(),
+        manifest: None,
+        domains,
+        summary,
+        stats: None,
+        estimate: None,
+        infra_report: None,
+        registrars: None,
+        input_files: Vec::new(),
+    }
+}
+
+impl CheckResult {
+    /// Strip everything that changes between identical runs and put the rest in a fixed order.
+    fn stabilize(&mut self) {
+        self.timestamp.clear();
+        self.stats = None;
+        if let Some(manifest) = &mut self.manifest {
+            manifest.started_at.clear();
+            manifest.finished_at = None;
+        }
+        self.domains.sort_by(|a, b| a.domain.cmp(&b.domain));
+        for status in &mut self.domains {
+            status.response_time_ms = 0;
+            status.checked_at.clear();
+            // The CNAME chain stays in resolution order, which is already deterministic
+            for records in [
+                &mut status.nameservers,
+                &mut status.ip_addresses,
+                &mut status.txt_records,
+                &mut status.mx_records,
+                &mut status.naptr_records,
+                &mut status.caa_records,
+                &mut status.reachable_nameservers,
+                &mut status.unreachable_nameservers,
+            ] {
+                records.sort();
+            }
+            for nameserver in &mut status.nameserver_ips {
+                nameserver.ips.sort();
+            }
+            status.divergent_nameservers.sort_by(|a, b| a.ns.cmp(&b.ns));
+            status.nameserver_ips.sort_by(|a, b| a.ns.cmp(&b.ns));
+            if let Some(full) = &mut status.full_response {
+                full.answers.sort();
+                full.authority.sort();
+                full.additional.sort();
+            }
+        }
+    }
+}
+
+/// Share of failed checks (see `DomainStatus::failed`) from which a run is put down to the
+/// resolver or its configuration rather than to the domains.
+const SYSTEMIC_FAILURE_RATE: f64 = 0.95;
+
+/// Fewest domains a run needs before a high failure rate is called systemic.
+const SYSTEMIC_FAILURE_MIN_DOMAINS: usize = 3;
+
+/// Failed and total checks when nearly all of them failed. Entries rejected as invalid were never
+/// sent to the resolver, so they count toward neither.
+fn systemic_failure(domains: &[DomainStatus]) -> Option<(usize, usize)> {
+    let checked = domains.iter().filter(|d| d.error_kind.as_deref() != Some("invalid_syntax"));
+    let (failed, total) = checked.fold((0, 0), |(failed, total), d| (failed + usize::from(d.failed()), total + 1));
+    (total >= SYSTEMIC_FAILURE_MIN_DOMAINS && failed as f64 >= total as f64 * SYSTEMIC_FAILURE_RATE)
+        .then_some((failed, total))
+}
+
+impl ResultSummary {
+    fn from_statuses<'a>(domains: impl IntoIterator<Item = &'a DomainStatus>) -> Self {
+        let mut summary = Self { total_checked: 0, registered: 0, unregistered: 0, errors: 0, unknown: 0 };
+        for d in domains {
+            summary.total_checked += 1;
+            if d.registered {
+                summary.registered += 1;
+            } else if !d.unknown {
+                summary.unregistered += 1;
+            }
+            if d.unknown {
+                summary.unknown += 1;
+            } else if d.error.is_some() {
+                summary.errors += 1;
+            }
+        }
+        summary
+    }
+}
+
+/// Summarizes each input file's domains against the results, which hold every domain once.
+fn summarize_input_files(files: &[(PathBuf, Vec<String>)], results: &[DomainStatus]) -> Vec<InputFileSummary> {
+    let by_domain: HashMap<&str, &DomainStatus> = results.iter().map(|d| (d.domain.as_str(), d)).collect();
+    files
+        .iter()
+        .map(|(path, domains)| InputFileSummary {
+            path: path.display().to_string(),
+            summary: ResultSummary::from_statuses(domains.iter().filter_map(|domain| by_domain.get(domain.as_str()).copied())),
+        })
+        .collect()
+}
+
+/// Which checked domains make it into the output.
+#[derive(Debug, Default)]
+struct ResultFilter {
+    unregistered_only: bool,
+    uncertain_only: bool,
+    matches: Vec<RecordMatch>,
+    /// Domains already reported by --notify-once
+    notified: Option<HashSet<String>>,
+}
+
+impl ResultFilter {
+    fn is_empty(&self) -> bool {
+        !self.unregistered_only && !self.uncertain_only && self.matches.is_empty() && self.notified.is_none()
+    }
+
+    fn keeps(&self, status: &DomainStatus) -> bool {
+        if self.unregistered_only && status.registered {
+            return false;
+        }
+        if self.uncertain_only && !status.is_uncertain() {
+            return false;
+        }
+        if let Some(notified) = &self.notified {
+            if !is_available(status) || notified.contains(&status.domain) {
+                return false;
+            }
+        }
+        self.matches.iter().all(|m| m.matches(status))
+    }
+}
+
+/// Unregistered by a definite answer, not because the lookup failed.
+fn is_available(status: &DomainStatus) -> bool {
+    !status.registered && !status.unknown && status.error.is_none()
+}
+
+fn read_notified(path: &std::path::Path) -> io::Result<HashSet<String>> {
+    match fs::File::open(path) {
+        Ok(file) => Ok(read_domains(io::BufReader::new(file), true, None)?.into_iter().collect()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(HashSet::new()),
+        Err(e) => Err(e),
+    }
+}
+
+impl From<&Cli> for ResultFilter {
+    fn from(cli: &Cli) -> Self {
+        Self {
+            unregistered_only: cli.unregistered_only,
+            uncertain_only: cli.uncertain_only,
+            matches: cli.matches.clone(),
+            notified: None,
+        }
+    }
+}
+
+fn filter_results(result: CheckResult, filter: &ResultFilter) -> CheckResult {
+    if filter.is_empty() {
+        return result;
+    }
+
+    // Keep all original summary counts
+    let ResultSummary {
+        total_checked,
+        registered,
+        unregistered,
+        errors: _,  // We'll recalculate errors for filtered domains
+        unknown,
+    } = result.summary;
+
+    let filtered_domains: Vec<DomainStatus> = result.domains
+        .into_iter()
+        .filter(|d| filter.keeps(d))
+        .collect();
+
+    // Only update errors count for the filtered domains
+    let errors = filtered_domains.iter().filter(|d| d.error.is_some() && !d.unknown).count();
+
+    CheckResult {
+        check_count: total_checked,
+        domains: filtered_domains,
+        summary: ResultSummary {
+            total_checked,
+            registered,
+            unregistered,
+            errors,
+            unknown,
+        },
+        ..result
+    }
+}
+
+/// Splits a result into registered, available and errored (or unknown) domains, each a result of
+/// its own carrying the run's timestamp and config.
+fn split_by_status(result: &CheckResult) -> [(&'static str, CheckResult); 3] {
+    let mut registered = Vec::new();
+    let mut available = Vec::new();
+    let mut errors = Vec::new();
+    for status in &result.domains {
+        if status.registered {
+            registered.push(status.clone());
+        } else if status.unknown || status.error.is_some() {
+            errors.push(status.clone());
+        } else {
+            available.push(status.clone());
+        }
+    }
+    [("registered", registered), ("available", available), ("errors", errors)].map(|(name, domains)| {
+        let mut bucket = create_check_result(domains, result.timestamp.clone());
+        bucket.config = result.config.clone();
+        (name, bucket)
+    })
+}
+
+/// Writes to a sibling temp file and renames it over `path`, so readers never see a partial file.
+fn write_atomic(path: &std::path::Path, contents: &str) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+
+    fs::write(&tmp, contents)?;
+    fs::rename(&tmp, path)
+}
+
+/// How the `domains` of a result are laid out in JSON output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum JsonLayout {
+    Array,
+    /// An object keyed by domain
+    Map,
+    /// An object keyed by TLD, each holding an object keyed by domain
+    ByTld,
+}
+
+impl From<&Cli> for JsonLayout {
+    fn from(cli: &Cli) -> Self {
+        if cli.json_by_tld {
+            Self::ByTld
+        } else if cli.json_map {
+            Self::Map
+        } else {
+            Self::Array
+        }
+    }
+}
+
+/// Converts a result to JSON, optionally replacing the `domains` array with an object keyed by
+/// domain (or by TLD, then domain). Also returns any domains that appeared more than once; the
+/// last occurrence wins.
+fn result_to_json(result: &CheckResult, layout: JsonLayout) -> serde_json::Result<(serde_json::Value, Vec<String>)> {
+    let mut value = serde_json::to_value(result)?;
+    let mut duplicates = Vec::new();
+
+    if layout != JsonLayout::Array {
+        let mut map = serde_json::Map::with_capacity(result.domains.len());
+        for status in &result.domains {
+            let parent = match layout {
+                JsonLayout::ByTld => map
+                    .entry(tld_of(&status.domain))
+                    .or_insert_with(|| serde_json::Value::Object(serde_json::Map::new()))
+                    .as_object_mut()
+                    .expect("TLD entries are objects"),
+                _ => &mut map,
+            };
+            if parent.insert(status.domain.clone(), serde_json::to_value(status)?).is_some() {
+                duplicates.push(status.domain.clone());
+            }
+        }
+        value["domains"] = serde_json::Value::Object(map);
+    }
+
+    Ok((value, duplicates))
+}
+
+/// Formats a domain as a logfmt line. Lists of scalars are joined with commas; nested objects
+/// and empty values are left out.
+fn logfmt_line(status: &DomainStatus) -> serde_json::Result<String> {
+    fn scalar(value: &serde_json::Value) -> Option<String> {
+        match value {
+            serde_json::Value::String(s) => Some(s.clone()),
+            serde_json::Value::Bool(_) | serde_json::Value::Number(_) => Some(value.to_string()),
+            _ => None,
+        }
+    }
+
+    let serde_json::Value::Object(fields) = serde_json::to_value(status)? else {
+        return Ok(String::new());
+    };
+    let mut pairs = Vec::new();
+    for (key, value) in &fields {
+        let value = match value {
+            serde_json::Value::Array(items) if !items.is_empty() => {
+                let items: Option<Vec<String>> = items.iter().map(scalar).collect();
+                match items {
+                    Some(items) => items.join(","),
+                    None => continue,
+                }
+            }
+            other => match scalar(other) {
+                Some(value) => value,
+                None => continue,
+            },
+        };
+        if value.is_empty() || value.contains([' ', '=', '"']) {
+            pairs.push(format!("{}={:?}", key, value));
+        } else {
+            pairs.push(format!("{}={}", key, value));
+        }
+    }
+    Ok(pairs.join(" "))
+}
+
+/// The result in a non-streaming format. `json` is the already laid-out JSON value, needed for
+/// `OutputFormat::Json`.
+fn render_output(
+    result: &CheckResult,
+    format: OutputFormat,
+    json: Option<&serde_json::Value>,
+) -> Result<String, Box<dyn std::error::Error>> {
+    Ok(match format {
+        OutputFormat::Json => serde_json::to_string_pretty(json.ok_or("JSON output was not prepared")?)?,
+        OutputFormat::Text => {
+            let mut out = Vec::new();
+            write_text_output(&mut out, result)?;
+            String::from_utf8(out)?
+        }
+        OutputFormat::Compact => compact_output(result),
+        OutputFormat::Logfmt => {
+            let mut out = String::new();
+            for status in &result.domains {
+                out.push_str(&logfmt_line(status)?);
+                out.push('\n');
+            }
+            out
+        }
+    })
+}
+
+/// Renders a partial result for --flush-every the way the final --output-file write will.
+fn render_file_output(
+    result: &CheckResult,
+    format: OutputFormat,
+    layout: JsonLayout,
+    stable: bool,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let json = match format {
+        OutputFormat::Json => {
+            let (mut value, _) = result_to_json(result, layout)?;
+            if stable {
+                value.sort_all_objects();
+            }
+            Some(value)
+        }
+        _ => None,
+    };
+    render_output(result, format, json.as_ref())
+}
+
+/// One aligned `domain  verdict` line per domain, then a one-line summary.
+fn compact_output(result: &CheckResult) -> String {
+    let width = result.domains.iter().map(|status| status.domain.len()).max().unwrap_or(0);
+    let mut out = String::new();
+    for status in &result.domains {
+        let verdict = if status.registered {
+            "registered".to_string()
+        } else if status.unknown {
+            "unknown".to_string()
+        } else if let Some(error) = &status.error {
+            format!("error: {}", error)
+        } else {
+            "unregistered".to_string()
+        };
+        out.push_str(&format!("{:<width$}  {}\n", status.domain, verdict));
+    }
+    let summary = &result.summary;
+    out.push_str(&format!(
+        "{} checked: {} registered, {} unregistered, {} errors{}\n",
+        summary.total_checked,
+        summary.registered,
+        summary.unregistered,
+        summary.errors,
+        if summary.unknown > 0 { format!(", {} unknown", summary.unknown) } else { String::new() }
+    ));
+    if let Some(stats) = &result.stats {
+        let latency = &stats.latency_ms;
+        out.push_str(&format!(
+            "latency ms: p50 {:.0}, p90 {:.0}, p99 {:.0} (min {:.0}, mean {:.1}, max {:.0}, {} samples)\n",
+            latency.p50, latency.p90, latency.p99, latency.min, latency.mean, latency.max, latency.count
+        ));
+        if stats.systemic_failure {
+            out.push_str("note: nearly every check failed, so the run likely hit a resolver problem\n");
+        }
+    }
+    out
+}
+
+/// Per-domain gauges in the OpenMetrics text exposition format.
+fn openmetrics(domains: &[DomainStatus]) -> String {
+    fn label(value: &str) -> String {
+        value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+    }
+
+    let mut out = String::new();
+    let mut gauge = |name: &str, help: &str, value: fn(&DomainStatus) -> u64| {
+        out.push_str(&format!("# TYPE {} gauge\n# HELP {} {}\n", name, name, help));
+        for status in domains {
+            out.push_str(&format!("{}{{domain=\"{}\"}} {}\n", name, label(&status.domain), value(status)));
+        }
+    };
+    gauge("domain_registered", "Whether the domain is registered (1) or not (0).", |d| d.registered as u64);
+    gauge("domain_response_time_ms", "Time taken to check the domain, in milliseconds.", |d| d.response_time_ms);
+    out.push_str("# EOF\n");
+    out
+}
+
+/// Pretty-prints JSON like `serde_json::to_string_pretty`, with ANSI colors for keys and scalars.
+fn colorize_json(value: &serde_json::Value) -> String {
+    fn write(value: &serde_json::Value, indent: usize, out: &mut String) {
+        const KEY: &str = "\x1b[34m";
+        const STRING: &str = "\x1b[32m";
+        const NUMBER: &str = "\x1b[36m";
+        const LITERAL: &str = "\x1b[33m";
+        const RESET: &str = "\x1b[0m";
+
+        let pad = |level: usize| "  ".repeat(level);
+        match value {
+            serde_json::Value::Null => out.push_str(&format!("{}null{}", LITERAL, RESET)),
+            serde_json::Value::Bool(b) => out.push_str(&format!("{}{}{}", LITERAL, b, RESET)),
+            serde_json::Value::Number(n) => out.push_str(&format!("{}{}{}", NUMBER, n, RESET)),
+            serde_json::Value::String(_) => out.push_str(&format!("{}{}{}", STRING, value, RESET)),
+            serde_json::Value::Array(items) if items.is_empty() => out.push_str("[]"),
+            serde_json::Value::Array(items) => {
+                out.push_str("[\n");
+                for (i, item) in items.iter().enumerate() {
+                    out.push_str(&pad(indent + 1));
+                    write(item, indent + 1, out);
+                    out.push_str(if i + 1 < items.len() { ",\n" } else { "\n" });
+                }
+                out.push_str(&format!("{}]", pad(indent)));
+            }
+            serde_json::Value::Object(map) if map.is_empty() => out.push_str("{}"),
+            serde_json::Value::Object(map) => {
+                out.push_str("{\n");
+                for (i, (key, item)) in map.iter().enumerate() {
+                    let key = serde_json::Value::String(key.clone());
+                    out.push_str(&format!("{}{}{}{}: ", pad(indent + 1), KEY, key, RESET));
+                    write(item, indent + 1, out);
+                    out.push_str(if i + 1 < map.len() { ",\n" } else { "\n" });
+                }
+                out.push_str(&format!("{}}}", pad(indent)));
+            }
+        }
+    }
+
+    let mut out = String::new();
+    write(value, 0, &mut out);
+    out
+}
+
+fn write_full_response(out: &mut impl io::Write, full: &FullResponse) -> io::Result<()> {
+    writeln!(
+        out,
+        "Full Response ({}, {}{}):",
+        full.server,
+        full.response_code,
+        if full.authoritative { ", authoritative" } else { "" }
+    )?;
+    for (section, records) in [
+        ("Answer", &full.answers),
+        ("Authority", &full.authority),
+        ("Additional", &full.additional),
+    ] {
+        for record in records {
+            writeln!(out, "  {}: {}", section, record)?;
+        }
+    }
+    if let Some(error) = &full.error {
+        writeln!(out, "  Error: {}", error)?;
+    }
+    Ok(())
+}
+
+fn print_wildcard_scope(scope: &WildcardScope) {
+    if !scope.wildcard {
+        println!("{}: no wildcard (a random label has no address records)", scope.domain);
+    } else {
+        println!("{}: wildcard answers {}", scope.domain, scope.wildcard_answer.join(", "));
+    }
+    let width = scope.labels.iter().map(|label| label.name.len()).max().unwrap_or(0);
+    for label in &scope.labels {
+        let detail = match (label.answer, &label.error) {
+            (_, Some(error)) => error.clone(),
+            (LabelAnswer::Distinct, _) => label.records.join(", "),
+            _ => String::new(),
+        };
+        let answer = match label.answer {
+            LabelAnswer::Wildcard => "wildcard",
+            LabelAnswer::Distinct => "distinct",
+            LabelAnswer::Absent => "absent",
+            LabelAnswer::Error => "error",
+        };
+        println!("{}", format!("  {:<width$}  {:<8}  {}", label.name, answer, detail).trim_end());
+    }
+}
+
+/// Sends one query of any class and type and prints the whole response.
+async fn run_query(cli: &Cli, name: &str, record_type: RecordType, class: QueryClass) -> Result<(), String> {
+    let server = match cli.nameservers.first() {
+        Some(server) => *server,
+        None => cli
+            .resolver
+            .config()?
+            .name_servers()
+            .first()
+            .map(|ns| ns.socket_addr)
+            .ok_or("resolver preset has no nameservers")?,
+    };
+    let name = Name::from_utf8(name).map_err(|e| format!("invalid name '{}': {}", name, e))?;
+    let mut query = Query::query(name, record_type);
+    query.set_query_class(class.into());
+
+    let response = match raw_query(server, query, !cli.no_recursion_desired, Timeouts::from(cli), cli.tsig_key.as_ref()).await {
+        Ok(message) => FullResponse::from_message(server, &message),
+        Err(e) => FullResponse {
+            server: server.to_string(),
+            error: Some(e.to_string()),
+            ..FullResponse::default()
+        },
+    };
+    if cli.json {
+        println!("{}", serde_json::to_string_pretty(&response).map_err(|e| e.to_string())?);
+    } else {
+        write_full_response(&mut io::stdout().lock(), &response).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+fn write_text_output(out: &mut impl io::Write, result: &CheckResult) -> io::Result<()> {
+    
+    if !result.timestamp.is_empty() {
+        writeln!(out, "\nTimestamp: {}", result.timestamp)?;
+    }
+    if let Some(seed) = result.config.seed {
+        writeln!(out, "Seed: {}", seed)?;
+    }
+    writeln!(out, "\nSummary:")?;
+    writeln!(out, "  Total Checked: {}", result.summary.total_checked)?;
+    writeln!(out, "  Registered: {}", result.summary.registered)?;
+    writeln!(out, "  Unregistered: {}", result.summary.unregistered)?;
+    writeln!(out, "  Errors: {}", result.summary.errors)?;
+    if result.summary.unknown > 0 {
+        writeln!(out, "  Unknown: {}", result.summary.unknown)?;
+    }
+
+    if !result.input_files.is_empty() {
+        let width = result.input_files.iter().map(|file| file.path.len()).max().unwrap_or(0).max(10);
+        writeln!(out, "\nPer-File Summary:")?;
+        writeln!(out, "  {:<width$}  {:>7}  {:>10}  {:>12}  {:>6}", "File", "Total", "Registered", "Unregistered", "Errors")?;
+        let rows = result.input_files.iter().map(|file| (file.path.as_str(), &file.summary));
+        for (path, summary) in rows.chain([("(combined)", &result.summary)]) {
+            writeln!(
+                out,
+                "  {:<width$}  {:>7}  {:>10}  {:>12}  {:>6}",
+                path, summary.total_checked, summary.registered, summary.unregistered, summary.errors
+            )?;
+        }
+    }
+
+    if let Some(estimate) = &result.estimate {
+        writeln!(out, "\nSample Estimate ({} of {} domains, {} with a verdict):", estimate.sampled, estimate.population, estimate.decided)?;
+        writeln!(
+            out,
+            "  Registration Rate: {:.1}% ({:.0}% CI {:.1}% - {:.1}%)",
+            estimate.registration_rate * 100.0,
+            estimate.confidence * 100.0,
+            estimate.ci_low * 100.0,
+            estimate.ci_high * 100.0
+        )?;
+        writeln!(
+            out,
+            "  Estimated Registered: ~{:.0} of {}",
+            estimate.registration_rate * estimate.population as f64,
+            estimate.population
+        )?;
+    }
+
+    if let Some(stats) = &result.stats {
+        let latency = &stats.latency_ms;
+        writeln!(out, "\nLatency (ms, {} samples):", latency.count)?;
+        writeln!(out, "  Min/Mean/Max: {:.0} / {:.1} / {:.0}", latency.min, latency.mean, latency.max)?;
+        writeln!(out, "  p50/p90/p99: {:.0} / {:.0} / {:.0}", latency.p50, latency.p90, latency.p99)?;
+        if stats.systemic_failure {
+            writeln!(out, "  Note: nearly every check failed, so the run likely hit a resolver problem")?;
+        }
+    }
+
+    if let Some(report) = &result.infra_report {
+        writeln!(out, "\nShared Infrastructure:")?;
+        for (label, shared) in [
+            ("Nameservers", &report.nameservers),
+            ("IPs", &report.ip_addresses),
+            ("MX", &report.mx_records),
+        ] {
+            writeln!(out, "  {}:", label)?;
+            if shared.is_empty() {
+                writeln!(out, "    (none shared)")?;
+            }
+            for infra in shared {
+                writeln!(out, "    {} ({} domains): {}", infra.value, infra.count, infra.domains.join(", "))?;
+            }
+        }
+    }
+
+    if let Some(registrars) = &result.registrars {
+        writeln!(out, "\nRegistrars:")?;
+        if registrars.is_empty() {
+            writeln!(out, "  (none found)")?;
+        }
+        for group in registrars {
+            writeln!(out, "  {} ({} domains): {}", group.registrar, group.count, group.domains.join(", "))?;
+        }
+    }
+
+    writeln!(out, "\nDetailed Results:")?;
+    for status in &result.domains {
+        writeln!(out, "\nDomain: {}", status.domain)?;
+        writeln!(out, "Registered: {}", status.registered)?;
+        if status.parked {
+            writeln!(out, "Parked: true")?;
+        }
+        if let Some(authoritative) = status.authoritative {
+            writeln!(out, "Authoritative: {}", authoritative)?;
+        }
+        if let Some(confidence) = status.consensus_confidence {
+            let uncertain = if status.uncertain { " (uncertain)" } else { "" };
+            writeln!(out, "Consensus Confidence: {:.2}{}", confidence, uncertain)?;
+        }
+        if let Some(decision) = &status.decision {
+            writeln!(
+                out,
+                "Decision: ns_present={} a_present={} aaaa_present={} nxdomain={} wildcard={}",
+                decision.ns_present, decision.a_present, decision.aaaa_present, decision.nxdomain, decision.wildcard
+            )?;
+        }
+        if let Some(confirmed) = status.nxdomain_confirmed {
+            writeln!(out, "NXDOMAIN Confirmed: {}{}", confirmed, if confirmed { "" } else { " (uncertain)" })?;
+        }
+        if let Some(cdn) = &status.cdn {
+            writeln!(out, "CDN: {}", cdn)?;
+        }
+        if let Some(answered_by) = &status.answered_by {
+            writeln!(out, "Answered By: {}", answered_by)?;
+        }
+        if status.blocklisted_ip {
+            writeln!(out, "Blocklisted IP: true")?;
+        }
+        if !status.assertions.is_empty() {
+            writeln!(out, "Assertions:")?;
+            for assertion in &status.assertions {
+                writeln!(out, "  {} {}", if assertion.passed { "PASS" } else { "FAIL" }, assertion.rule)?;
+            }
+        }
+        if let Some(compliant) = status.caa_compliant {
+            writeln!(out, "CAA Compliant: {}", compliant)?;
+        }
+        if let Some(matches) = status.ip_matches_expected {
+            writeln!(out, "IPs Match Expected: {}", matches)?;
+        }
+
+        if !status.nameservers.is_empty() {
+            writeln!(out, "Nameservers:")?;
+            for ns in &status.nameservers {
+                writeln!(out, "  - {}", ns)?;
+            }
+        }
+
+        if !status.txt_records.is_empty() {
+            writeln!(out, "TXT Records:")?;
+            for txt in &status.txt_records {
+                writeln!(out, "  - {}", txt)?;
+            }
+        }
+
+        if let Some(spf) = &status.spf {
+            writeln!(out, "SPF: {}", spf)?;
+            for include in &status.spf_includes {
+                writeln!(out, "  include:{} -> {}", include.domain, include.spf.as_deref().unwrap_or("(no SPF record)"))?;
+            }
+        }
+        if let Some(policy) = &status.dmarc_policy {
+            writeln!(out, "DMARC Policy: {}", policy)?;
+        }
+
+        if let Some(dname) = &status.dname {
+            writeln!(out, "DNAME: {} (subdomains are redirected)", dname)?;
+        }
+
+        if !status.naptr_records.is_empty() {
+            writeln!(out, "NAPTR Records:")?;
+            for naptr in &status.naptr_records {
+                writeln!(out, "  - {}", naptr)?;
+            }
+        }
+
+        if !status.caa_records.is_empty() {
+            writeln!(out, "CAA Records:")?;
+            for caa in &status.caa_records {
+                writeln!(out, "  - {}", caa)?;
+            }
+        }
+
+        if !status.mx_records.is_empty() {
+            writeln!(out, "MX Records:")?;
+            for mx in &status.mx_records {
+                writeln!(out, "  - {}", mx)?;
+            }
+        }
+
+        if !status.nameserver_ips.is_empty() {
+            writeln!(out, "Nameserver IPs:")?;
+            for entry in &status.nameserver_ips {
+                writeln!(out, "  - {}: {}", entry.ns, entry.ips.join(", "))?;
+            }
+        }
+
+        if !status.unreachable_nameservers.is_empty() {
+            writeln!(out, "Unreachable Nameservers:")?;
+            for ns in &status.unreachable_nameservers {
+                writeln!(out, "  - {}", ns)?;
+            }
+        }
+
+        if let Some(consistent) = status.ns_consistent {
+            writeln!(out, "NS Consistent: {}", consistent)?;
+            for divergent in &status.divergent_nameservers {
+                writeln!(out, "  - {} serves: {}", divergent.ns, divergent.records.join(", "))?;
+            }
+        }
+
+        if !status.vantages.is_empty() {
+            writeln!(out, "Vantages:")?;
+            for vantage in &status.vantages {
+                match &vantage.error {
+                    Some(error) => writeln!(out, "  - {}: error: {}", vantage.region, error)?,
+                    None if vantage.ips.is_empty() => writeln!(out, "  - {}: no addresses", vantage.region)?,
+                    None => writeln!(out, "  - {}: {}", vantage.region, vantage.ips.join(", "))?,
+                }
+            }
+            writeln!(out, "Geo Variance: {}", status.geo_variance)?;
+        }
+
+        if !status.ip_addresses.is_empty() {
+            writeln!(out, "IP Addresses:")?;
+            for ip in &status.ip_addresses {
+                writeln!(out, "  - {}", ip)?;
+            }
+        }
+
+        if let Some(full) = &status.full_response {
+            write_full_response(out, full)?;
+        }
+
+        if let Some(expiry) = &status.cert_expiry {
+            writeln!(out, "Certificate Expires: {}", expiry)?;
+            writeln!(out, "Redirects to HTTPS: {}", status.redirects_to_https)?;
+        }
+
+        if let Some(registrar) = &status.registrar {
+            writeln!(out, "Registrar: {}", registrar)?;
+        }
+        if let Some(expires_at) = &status.expires_at {
+            writeln!(out, "Expires: {}", expires_at)?;
+        }
+        if let Some(drop_eta) = &status.drop_eta {
+            writeln!(out, "Drop ETA: {}", drop_eta)?;
+        }
+        if let Some(error) = &status.rdap_error {
+            writeln!(out, "RDAP Error: {}", error)?;
+        }
+        if !status.verdict_source.is_empty() {
+            writeln!(out, "Verdict Source: {}", status.verdict_source)?;
+        }
+        for warning in &status.warnings {
+            writeln!(out, "Warning: {}", warning)?;
+        }
+        if let Some(error) = &status.http_error {
+            writeln!(out, "HTTP Error: {}", error)?;
+        }
+
+        if let Some(error) = &status.error {
+            writeln!(out, "Error: {}", error)?;
+        }
+    }
+    Ok(())
+}
+
+/// Checks hostname syntax: 1-63 character labels of letters, digits and inner hyphens, at most 253 characters overall.
+fn validate_domain(domain: &str) -> Result<(), String> {
+    let name = domain.strip_suffix('.').unwrap_or(domain);
+
+    if name.is_empty() {
+        return Err("empty name".to_string());
+    }
+    if name.len() > 253 {
+        return Err("name longer than 253 characters".to_string());
+    }
+    if !name.contains('.') {
+        return Err("missing top-level domain".to_string());
+    }
+
+    for label in name.split('.') {
+        if label.is_empty() {
+            return Err("empty label".to_string());
+        }
+        if label.len() > 63 {
+            return Err(format!("label '{}' longer than 63 characters", label));
+        }
+        if label.starts_with('-') || label.ends_with('-') {
+            return Err(format!("label '{}' starts or ends with a hyphen", label));
+        }
+        if let Some(c) = label.chars().find(|c| !(c.is_alphanumeric() || *c == '-')) {
+            return Err(format!("invalid character '{}' in label '{}'", c, label));
+        }
+    }
+
+    Ok(())
+}
+
+/// One line of `--jsonl` input.
+#[derive(Debug, Deserialize)]
+struct InputRecord {
+    domain: String,
+    #[serde(default)]
+    expected_ips: Vec<IpAddr>,
+    #[serde(default)]
+    metadata: Option<serde_json::Value>,
+    /// Any other fields, echoed back as `metadata` when there's no explicit one
+    #[serde(flatten)]
+    extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl InputRecord {
+    fn metadata(&mut self) -> Option<serde_json::Value> {
+        self.metadata
+            .take()
+            .or_else(|| (!self.extra.is_empty()).then(|| serde_json::Value::Object(std::mem::take(&mut self.extra))))
+    }
+}
+
+fn read_records_from_stdin() -> io::Result<Vec<InputRecord>> {
+    let mut records = Vec::new();
+
+    for (index, line) in io::stdin().lock().lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record = serde_json::from_str(&line).map_err(|e| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", index + 1, e))
+        })?;
+        records.push(record);
+    }
+
+    Ok(records)
+}
+
+/// True when the resolved addresses are exactly the expected set.
+fn ips_match(actual: &[String], expected: &[IpAddr]) -> bool {
+    let actual: std::collections::HashSet<IpAddr> = actual.iter().filter_map(|ip| ip.parse().ok()).collect();
+    let expected: std::collections::HashSet<IpAddr> = expected.iter().copied().collect();
+    actual == expected
+}
+
+/// Reads a blocklist of CIDRs or bare addresses, skipping blank lines and `#` comments.
+fn read_ip_blocklist(path: &Path) -> Result<Vec<IpNet>, String> {
+    let contents = fs::read_to_string(path).map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+    let mut blocklist = Vec::new();
+    for (number, line) in contents.lines().enumerate() {
+        let entry = line.split('#').next().unwrap_or("").trim();
+        if entry.is_empty() {
+            continue;
+        }
+        let net = entry
+            .parse::<IpNet>()
+            .or_else(|_| entry.parse::<IpAddr>().map(IpNet::from))
+            .map_err(|_| format!("{}:{}: invalid CIDR or IP '{}'", path.display(), number + 1, entry))?;
+        blocklist.push(net);
+    }
+    Ok(blocklist)
+}
+
+fn is_blocklisted(ips: &[String], blocklist: &[IpNet]) -> bool {
+    ips.iter()
+        .filter_map(|ip| ip.parse::<IpAddr>().ok())
+        .any(|ip| blocklist.iter().any(|net| net.contains(&ip)))
+}
+
+/// Chance that --dedup-approx takes a domain it hasn't seen for a duplicate, over the whole input.
+const DEDUP_FALSE_POSITIVE_RATE: f64 = 0.0001;
+
+/// Domains the first --dedup-approx stage holds before a bigger one is added.
+const DEDUP_INITIAL_CAPACITY: usize = 1 << 16;
+
+/// A Bloom filter that grows by adding stages, each twice the capacity of the last with half the
+/// false positive rate, so the overall rate stays under the target however many items go in.
+struct ScalableBloom {
+    stages: Vec<BloomStage>,
+    next_rate: f64,
+    /// Items turned away as (probably) already present.
+    rejected: usize,
+}
+
+struct BloomStage {
+    bits: Vec<u64>,
+    hashes: u64,
+    capacity: usize,
+    len: usize,
+}
+
+impl BloomStage {
+    fn new(capacity: usize, rate: f64) -> Self {
+        let ln2 = std::f64::consts::LN_2;
+        let bits = (-(capacity as f64) * rate.ln() / (ln2 * ln2)).ceil().max(64.0) as usize;
+        let hashes = ((bits as f64 / capacity as f64) * ln2).ceil().max(1.0) as u64;
+        Self { bits: vec![0; bits.div_ceil(64)], hashes, capacity, len: 0 }
+    }
+
+    /// Bit positions for an item by double hashing.
+    fn positions(&self, (h1, h2): (u64, u64)) -> impl Iterator<Item = usize> {
+        let size = (self.bits.len() * 64) as u64;
+        (0..self.hashes).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % size) as usize)
+    }
+
+    fn contains(&self, hash: (u64, u64)) -> bool {
+        self.positions(hash).all(|bit| self.bits[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+
+    fn insert(&mut self, hash: (u64, u64)) {
+        for bit in self.positions(hash).collect::<Vec<_>>() {
+            self.bits[bit / 64] |= 1 << (bit % 64);
+        }
+        self.len += 1;
+    }
+}
+
+impl ScalableBloom {
+    fn new(rate: f64) -> Self {
+        // Halving the rate per stage keeps the sum of all stages' rates under `rate`
+        Self { stages: Vec::new(), next_rate: rate / 2.0, rejected: 0 }
+    }
+
+    fn hash(item: &str) -> (u64, u64) {
+        use std::hash::{Hash, Hasher};
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        item.hash(&mut hasher);
+        let h1 = hasher.finish();
+        h1.hash(&mut hasher);
+        (h1, hasher.finish() | 1)
+    }
+
+    /// Adds the item, returning false if it was (probably) already present.
+    fn insert(&mut self, item: &str) -> bool {
+        let hash = Self::hash(item);
+        if self.stages.iter().any(|stage| stage.contains(hash)) {
+            self.rejected += 1;
+            return false;
+        }
+        if self.stages.last().is_none_or(|stage| stage.len >= stage.capacity) {
+            let capacity = self.stages.last().map_or(DEDUP_INITIAL_CAPACITY, |stage| stage.capacity * 2);
+            self.stages.push(BloomStage::new(capacity, self.next_rate));
+            self.next_rate /= 2.0;
+        }
+        if let Some(stage) = self.stages.last_mut() {
+            stage.insert(hash);
+        }
+        true
+    }
+}
+
+/// Reads one domain per line. With `seen`, a line the filter has already seen is dropped before it
+/// is stored.
+fn read_domains(reader: impl BufRead, clean: bool, mut seen: Option<&mut ScalableBloom>) -> io::Result<Vec<String>> {
+    let mut domains = Vec::new();
+
+    for line in reader.lines() {
+        let line = line?;
+        let domain = if clean {
+            let trimmed = line.trim();
+            if trimmed.is_empty() {
+                continue;
+            }
+            trimmed.to_string()
+        } else {
+            line
+        };
+        if seen.as_mut().is_none_or(|seen| seen.insert(&domain)) {
+            domains.push(domain);
+        }
+    }
+
+    Ok(domains)
+}
+
+/// Body of an --import-url response: either a bare list of names or one wrapped in an object.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ImportedDomains {
+    List(Vec<String>),
+    Wrapped { domains: Vec<String> },
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct DomainStatus {
-    domain: String,
-    registered: bool,
-    has_dns: bool,
-    has_ip: bool,
-    nameservers: Vec<String>,
-    ip_addresses: Vec<String>,
-    error: Option<String>,
+/// Fetches the --import-url domain list, trimming and dropping blank names under --clean the
+/// way line input is.
+async fn import_domains(
+    url: &str,
+    token: Option<&str>,
+    clean: bool,
+    connect_timeout: Duration,
+) -> Result<Vec<String>, String> {
+    let http = reqwest::Client::builder()
+        .connect_timeout(connect_timeout)
+        .timeout(Duration::from_secs(30))
+        .build()
+        .map_err(|e| e.to_string())?;
+    let mut request = http.get(url).header(reqwest::header::ACCEPT, "application/json");
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    let imported: ImportedDomains = request
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| format!("failed to fetch domains: {}", e))?
+        .json()
+        .await
+        .map_err(|_| "expected a JSON array of domains or an object with a \"domains\" array".to_string())?;
+
+    let (ImportedDomains::List(domains) | ImportedDomains::Wrapped { domains }) = imported;
+    if !clean {
+        return Ok(domains);
+    }
+    Ok(domains
+        .iter()
+        .map(|domain| domain.trim())
+        .filter(|domain| !domain.is_empty())
+        .map(str::to_string)
+        .collect())
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct CheckResult {
-    timestamp: String,
-    check_count: usize,
-    domains: Vec<DomainStatus>,
-    summary: ResultSummary,
+/// One row of a --brand-csv file.
+struct BrandVariant {
+    brand: String,
+    locale: String,
+    name: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct ResultSummary {
-    total_checked: usize,
-    registered: usize,
-    unregistered: usize,
-    errors: usize,
+/// Splits a CSV line on commas outside double quotes; `""` inside quotes is a literal quote.
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                fields.last_mut().unwrap().push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(String::new()),
+            _ => fields.last_mut().unwrap().push(c),
+        }
+    }
+    fields.into_iter().map(|field| field.trim().to_string()).collect()
 }
 
-struct DomainChecker {
-    resolver: TokioAsyncResolver,
+fn read_brand_csv(reader: impl BufRead) -> io::Result<Vec<BrandVariant>> {
+    let mut variants = Vec::new();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let fields = split_csv_line(&line);
+        let [brand, locale, name] = fields.as_slice() else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("line {}: expected brand,locale,localized_name", index + 1),
+            ));
+        };
+        if index == 0 && brand.eq_ignore_ascii_case("brand") && name.eq_ignore_ascii_case("localized_name") {
+            continue;
+        }
+        variants.push(BrandVariant {
+            brand: brand.clone(),
+            locale: locale.clone(),
+            name: name.trim_end_matches('.').to_lowercase(),
+        });
+    }
+    Ok(variants)
 }
 
-impl DomainChecker {
-    async fn new() -> Self {
-        let mut opts = ResolverOpts::default();
-        opts.timeout = Duration::from_secs(2);
-        opts.attempts = 2;
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cli = Cli::parse();
 
-        let resolver = TokioAsyncResolver::tokio(
-            ResolverConfig::cloudflare(),
-            opts,
-        );
+    match &cli.command {
+        Some(Command::ListResolvers) => {
+            list_resolvers(cli.resolver, &cli.nameservers);
+            return Ok(());
+        }
+        Some(Command::ReadMsgpack { path }) => {
+            let result: CheckResult = rmp_serde::from_slice(&fs::read(path)?)?;
+            println!("{}", serde_json::to_string_pretty(&result)?);
+            return Ok(());
+        }
+        Some(Command::Query { name, record_type, class }) => {
+            if let Err(e) = run_query(&cli, name, *record_type, *class).await {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Some(Command::WildcardScope { .. }) | None => {}
+    }
 
-        Self { resolver }
+    if cli.show_tld_concurrency {
+        for (tld, limit) in effective_tld_concurrency(&cli.tld_concurrency) {
+            println!("{}\t{}", tld, limit);
+        }
+        return Ok(());
     }
 
-    async fn check_domain(&self, domain: String) -> DomainStatus {
-        let mut status = DomainStatus {
-            domain,
-            registered: false,
-            has_dns: false,
-            has_ip: false,
-            nameservers: Vec::new(),
-            ip_addresses: Vec::new(),
-            error: None,
+    let options = CheckOptions::from(&cli);
+    let mut checker = match DomainChecker::new(cli.nameservers.clone(), cli.skip_unreachable_resolvers, options).await {
+        Ok(checker) => checker,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if let Some(Command::WildcardScope { domain, labels }) = &cli.command {
+        let labels = if labels.is_empty() {
+            WILDCARD_PROBE_LABELS.iter().map(|label| label.to_string()).collect()
+        } else {
+            labels.clone()
         };
+        let scope = checker.wildcard_scope(domain, &labels).await;
+        if cli.json {
+            println!("{}", serde_json::to_string_pretty(&scope)?);
+        } else {
+            print_wildcard_scope(&scope);
+        }
+        return Ok(());
+    }
 
-        // Check NS records
-        match self.resolver.ns_lookup(status.domain.clone()).await {
-            Ok(ns_records) => {
-                status.has_dns = true;
-                status.registered = true;
-                status.nameservers = ns_records
-                    .iter()
-                    .map(|record| record.to_string())
-                    .collect();
+    let blocklist = match &cli.ip_blocklist {
+        Some(path) => match read_ip_blocklist(path) {
+            Ok(blocklist) => blocklist,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
             }
-            Err(e) => match e.kind() {
-                trust_dns_resolver::error::ResolveErrorKind::NoRecordsFound { .. } => {}
-                _ => {
-                    if !status.registered {
-                        status.error = Some(format!("NS lookup error: {}", e));
-                    }
-                }
-            },
-        }
+        },
+        None => Vec::new(),
+    };
 
-        // Check A records
-        match self.resolver.lookup_ip(status.domain.clone()).await {
-            Ok(ips) => {
-                status.has_ip = true;
-                status.registered = true;
-                status.ip_addresses = ips
-                    .iter()
-                    .map(|ip| ip.to_string())
-                    .collect();
+    let mut replayed_domains = Vec::new();
+    if let Some(path) = &cli.replay {
+        match read_replay(path) {
+            Ok(statuses) => {
+                replayed_domains = statuses.iter().map(|status| status.domain.clone()).collect();
+                checker.replay_from(statuses);
             }
             Err(e) => {
-                if !status.registered {
-                    match e.kind() {
-                        trust_dns_resolver::error::ResolveErrorKind::NoRecordsFound { .. } => {}
-                        _ => {
-                            status.error = Some(format!("IP lookup error: {}", e));
-                        }
-                    }
-                }
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
             }
         }
-
-        status
     }
 
-    async fn check_domains(&self, domains: Vec<String>, concurrent_limit: usize) -> Vec<DomainStatus> {
-        stream::iter(domains)
-            .map(|domain| self.check_domain(domain))
-            .buffer_unordered(concurrent_limit)
-            .collect()
-            .await
-    }
-}
+    // Expected addresses and passthrough metadata per domain, from --jsonl input
+    let mut expected_ips: HashMap<String, Vec<IpAddr>> = HashMap::new();
+    let mut metadata: HashMap<String, serde_json::Value> = HashMap::new();
 
-fn create_check_result(domains: Vec<DomainStatus>, timestamp: String) -> CheckResult {
-    let total_checked = domains.len();
-    let registered = domains.iter().filter(|d| d.registered).count();
-    let unregistered = domains.iter().filter(|d| !d.registered).count();
-    let errors = domains.iter().filter(|d| d.error.is_some()).count();
+    // Domains per --input-file, for the per-file summaries
+    let mut input_files = Vec::new();
+    let domains = if let Some(path) = &cli.brand_csv {
+        let variants = match fs::File::open(path).and_then(|file| read_brand_csv(io::BufReader::new(file))) {
+            Ok(variants) => variants,
+            Err(e) => {
+                eprintln!("Error: {}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        };
+        let mut domains = Vec::new();
+        for variant in &variants {
+            for tld in &cli.tlds {
+                let domain = format!("{}.{}", variant.name, tld.trim_matches('.').to_ascii_lowercase());
+                // A spelling shared by several markets is checked once, tagged with the first
+                if !metadata.contains_key(&domain) {
+                    let tags = serde_json::json!({ "brand": variant.brand, "locale": variant.locale });
+                    metadata.insert(domain.clone(), tags);
+                    domains.push(domain);
+                }
+            }
+        }
+        domains
+    } else if let Some(url) = &cli.import_url {
+        match import_domains(url, cli.import_token.as_deref(), cli.clean, Timeouts::from(&cli).connect).await {
+            Ok(domains) => domains,
+            Err(e) => {
+                eprintln!("Error: {}: {}", url, e);
+                std::process::exit(1);
+            }
+        }
+    } else if cli.domains.is_empty() && !cli.input_files.is_empty() {
+        let mut seen = HashSet::new();
+        let mut domains = Vec::new();
+        for path in &cli.input_files {
+            let file_domains = match fs::File::open(path).and_then(|file| read_domains(io::BufReader::new(file), cli.clean, None)) {
+                Ok(file_domains) => file_domains,
+                Err(e) => {
+                    eprintln!("Error: {}: {}", path.display(), e);
+                    std::process::exit(1);
+                }
+            };
+            domains.extend(file_domains.iter().filter(|domain| seen.insert(domain.to_string())).cloned());
+            input_files.push((path.clone(), file_domains));
+        }
+        domains
+    } else if cli.domains.is_empty() && cli.replay.is_some() && !cli.jsonl {
+        replayed_domains
+    } else if cli.domains.is_empty() && cli.jsonl {
+        let mut domains = Vec::new();
+        for mut record in read_records_from_stdin()? {
+            if let Some(value) = record.metadata() {
+                metadata.insert(record.domain.clone(), value);
+            }
+            if !record.expected_ips.is_empty() {
+                expected_ips.insert(record.domain.clone(), record.expected_ips);
+            }
+            domains.push(record.domain);
+        }
+        domains
+    } else if cli.domains.is_empty() {
+        // No domains provided as arguments, try reading from stdin
+        let mut seen = cli.dedup_approx.then(|| ScalableBloom::new(DEDUP_FALSE_POSITIVE_RATE));
+        let domains = read_domains(io::stdin().lock(), cli.clean, seen.as_mut())?;
+        if let Some(seen) = seen.filter(|seen| seen.rejected > 0) {
+            eprintln!("Dropped {} duplicate domain(s)", seen.rejected);
+        }
+        domains
+    } else {
+        std::mem::take(&mut cli.domains)
+    };
 
-    CheckResult {
-        timestamp,
-        check_count: total_checked,
-        domains,
-        summary: ResultSummary {
-            total_checked,
-            registered,
-            unregistered,
-            errors,
+    // Priority domains are checked in their own lane, so drop them from the main list
+    let priority = match &cli.priority_file {
+        Some(path) => match fs::File::open(path).and_then(|file| read_domains(io::BufReader::new(file), cli.clean, None)) {
+            Ok(priority) => priority,
+            Err(e) => {
+                eprintln!("Error: {}: {}", path.display(), e);
+                std::process::exit(1);
+            }
         },
+        None => Vec::new(),
+    };
+    // Hashed in read order, with only the priority names the input lacks added, so the same input
+    // hashes the same whatever is moved into the priority lane
+    let read: HashSet<&String> = if priority.is_empty() { HashSet::new() } else { domains.iter().collect() };
+    let extra_priority = priority.iter().filter(|domain| !read.contains(domain));
+    let mut manifest = Manifest::new(domains.iter().chain(extra_priority), Utc::now().to_rfc3339());
+    let priority_set: HashSet<String> = priority.iter().cloned().collect();
+    let domains: Vec<String> = if priority.is_empty() {
+        domains
+    } else {
+        domains.into_iter().filter(|domain| !priority_set.contains(domain)).collect()
+    };
+
+    // Verify we have domains to check
+    if domains.is_empty() && priority.is_empty() {
+        eprintln!("Error: No domains provided. Either specify domains as arguments or pipe them through stdin.");
+        std::process::exit(1);
     }
-}
 
-fn filter_results(result: CheckResult, unregistered_only: bool) -> CheckResult {
-    if !unregistered_only {
-        return result;
+    let mut filter = ResultFilter::from(&cli);
+    if let Some(path) = &cli.notify_once {
+        match read_notified(path) {
+            Ok(notified) => filter.notified = Some(notified),
+            Err(e) => {
+                eprintln!("Error: {}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        }
+    }
+    let mut config = RunConfig::default();
+    let mut domains = domains;
+    let population = domains.len();
+    if cli.shuffle || cli.sample.is_some() {
+        let seed = cli.seed.unwrap_or_else(rand::random);
+        let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+        if let Some(n) = cli.sample {
+            // The chosen domains come out in random order, so --shuffle has nothing left to do
+            let n = (n as usize).min(domains.len());
+            domains = domains.partial_shuffle(&mut rng, n).0.to_vec();
+            config.sample = Some(n);
+        } else {
+            domains.shuffle(&mut rng);
+        }
+        config.seed = Some(seed);
     }
 
-    // Keep all original summary counts
-    let ResultSummary {
-        total_checked,
-        registered,
-        unregistered,
-        errors: _,  // We'll recalculate errors for filtered domains
-    } = result.summary;
+    // Results that are final without a full check
+    let mut settled = Vec::new();
+    let mut validate = |domains: Vec<String>| {
+        if !(cli.validate || cli.keep_invalid) {
+            return domains;
+        }
+        let mut valid = Vec::with_capacity(domains.len());
+        for domain in domains {
+            match validate_domain(&domain) {
+                Ok(()) => valid.push(domain),
+                Err(reason) if cli.keep_invalid => settled.push(DomainStatus::invalid(domain, reason)),
+                Err(reason) => eprintln!("Warning: skipping invalid domain '{}': {}", domain, reason),
+            }
+        }
+        valid
+    };
+    let priority = validate(priority);
+    let domains = validate(domains);
 
-    let filtered_domains: Vec<DomainStatus> = result.domains
-        .into_iter()
-        .filter(|d| !d.registered)
-        .collect();
+    let webhook = match cli.webhook.clone() {
+        Some(url) => match Webhook::new(
+            url,
+            cli.webhook_batch_size,
+            Duration::from_millis(cli.webhook_flush_ms),
+            Timeouts::from(&cli).connect,
+        ) {
+            Ok(webhook) => Some(webhook.spawn(cli.output_buffer)),
+            Err(e) => {
+                eprintln!("Error: failed to set up the webhook: {}", e);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
 
-    // Only update errors count for the filtered domains
-    let errors = filtered_domains.iter().filter(|d| d.error.is_some()).count();
+    // In two-phase mode, domains without NS records are final after the sweep
+    let domains = if cli.two_phase {
+        let (survivors, swept): (Vec<_>, Vec<_>) = checker
+            .sweep_ns(domains, cli.concurrent)
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .partition(|status| !checker.settled_by_sweep(status));
+        settled.extend(swept);
+        survivors.into_iter().map(|status| status.domain).collect()
+    } else {
+        domains
+    };
 
-    CheckResult {
-        timestamp: result.timestamp,
-        check_count: total_checked,
-        domains: filtered_domains,
-        summary: ResultSummary {
-            total_checked,
-            registered,
-            unregistered,
-            errors,
+    let sse = match cli.serve {
+        Some(addr) => match SseServer::bind(addr).await {
+            Ok(server) => {
+                let (tx, first_client, handle) = server.spawn(cli.output_buffer);
+                if cli.serve_wait {
+                    eprintln!("Waiting for a client to connect to http://{}/", addr);
+                    let _ = first_client.await;
+                }
+                Some((tx, handle))
+            }
+            Err(e) => {
+                eprintln!("Error: failed to listen on {}: {}", addr, e);
+                std::process::exit(1);
+            }
         },
+        None => None,
+    };
+
+    let total = settled.len() + priority.len() + domains.len();
+    if cli.events {
+        Event::Started { total }.emit();
     }
-}
+    Event::Started { total }.broadcast(&sse).await;
 
-fn print_text_output(result: &CheckResult) {
-    
-    println!("\nTimestamp: {}", result.timestamp);    
-    println!("\nSummary:");
-    println!("  Total Checked: {}", result.summary.total_checked);
-    println!("  Registered: {}", result.summary.registered);
-    println!("  Unregistered: {}", result.summary.unregistered);
-    println!("  Errors: {}", result.summary.errors);
-
-    println!("\nDetailed Results:");
-    for status in &result.domains {
-        println!("\nDomain: {}", status.domain);
-        println!("Registered: {}", status.registered);
+    let file_format = cli
+        .output_file
+        .as_deref()
+        .map(|path| cli.output_format.unwrap_or_else(|| OutputFormat::from_path(path)));
+    let mut latency = cli.stats.then(LatencyAggregator::new);
+    let mut results = Vec::with_capacity(domains.len());
 
-        if !status.nameservers.is_empty() {
-            println!("Nameservers:");
-            for ns in &status.nameservers {
-                println!("  - {}", ns);
+    // Called once a result has been emitted, so progress never runs ahead of what was reported
+    let done = std::sync::Arc::new(AtomicUsize::new(0));
+    let progress: ProgressCallback = {
+        let done = std::sync::Arc::clone(&done);
+        let events = cli.events;
+        std::sync::Arc::new(move |reported, total| {
+            done.store(reported, Ordering::Relaxed);
+            if events {
+                Event::Progress { done: reported, total }.emit();
+            }
+        })
+    };
+    let mut dashboard = if cli.tui && io::stdout().is_terminal() {
+        match Dashboard::new(total, std::sync::Arc::clone(&done)) {
+            Ok(dashboard) => Some(dashboard),
+            Err(e) => {
+                eprintln!("Warning: failed to start the dashboard: {}", e);
+                None
             }
         }
+    } else {
+        None
+    };
+    let mut redraw = tokio::time::interval(Duration::from_millis(250));
 
-        if !status.ip_addresses.is_empty() {
-            println!("IP Addresses:");
-            for ip in &status.ip_addresses {
-                println!("  - {}", ip);
+    let mut statuses = Box::pin(
+        stream::iter(settled)
+            .chain(checker.check_domains(priority, cli.priority_concurrent, None))
+            .chain(checker.check_domains(domains, cli.concurrent, None)),
+    );
+    let mut auto_recover = cli.auto_recover;
+    let mut failure_streak: Vec<DomainStatus> = Vec::new();
+    loop {
+        let next = tokio::select! {
+            next = statuses.next() => next,
+            _ = redraw.tick(), if dashboard.is_some() => {
+                if let Some(dashboard) = &mut dashboard {
+                    let _ = dashboard.draw();
+                }
+                continue;
+            }
+            _ = tokio::signal::ctrl_c(), if dashboard.is_some() => {
+                drop(dashboard);
+                std::process::exit(130);
+            }
+        };
+        let finished = next.is_none();
+        let ready = match next {
+            // Failures are held back until the streak either breaks or looks like an outage
+            Some(status) if auto_recover && is_lookup_failure(&status) => {
+                failure_streak.push(status);
+                if failure_streak.len() < RECOVER_STREAK {
+                    continue;
+                }
+                eprintln!(
+                    "Warning: the last {} lookups all failed, pausing until the resolver answers (up to {}s)",
+                    failure_streak.len(),
+                    cli.recover_max_wait.as_secs()
+                );
+                if checker.wait_for_resolver(cli.recover_max_wait).await {
+                    let failed: Vec<String> = failure_streak.drain(..).map(|status| status.domain).collect();
+                    eprintln!("Resolver is answering again, re-checking {} domain(s)", failed.len());
+                    checker.check_domains(failed, cli.concurrent, None).collect::<Vec<_>>().await
+                } else {
+                    eprintln!(
+                        "Warning: resolver still unreachable after {}s, continuing without --auto-recover",
+                        cli.recover_max_wait.as_secs()
+                    );
+                    auto_recover = false;
+                    std::mem::take(&mut failure_streak)
+                }
+            }
+            Some(status) => {
+                let mut ready = std::mem::take(&mut failure_streak);
+                ready.push(status);
+                ready
+            }
+            None => std::mem::take(&mut failure_streak),
+        };
+        for mut status in ready {
+            if let Some(expected) = expected_ips.get(&status.domain) {
+                status.ip_matches_expected = Some(ips_match(&status.ip_addresses, expected));
+            }
+            if let Some(value) = metadata.get(&status.domain) {
+                status.metadata = Some(value.clone());
+            }
+            status.blocklisted_ip = is_blocklisted(&status.ip_addresses, &blocklist);
+            status.assertions = cli.assertions.iter().map(|assertion| assertion.evaluate(&status)).collect();
+            // Awaiting a full queue stops this loop polling the check stream, so no new
+            // domains are started until the sink catches up
+            if let Some((tx, _)) = &webhook {
+                let _ = tx.send(status.clone()).await;
+            }
+            if let Some(latency) = &mut latency {
+                latency.record(&status);
+            }
+            if cli.events && filter.keeps(&status) {
+                Event::Result(&status).emit();
             }
+            if filter.keeps(&status) {
+                Event::Result(&status).broadcast(&sse).await;
+            }
+            progress(results.len() + 1, total);
+            Event::Progress { done: results.len() + 1, total }.broadcast(&sse).await;
+            if let Some(dashboard) = &mut dashboard {
+                dashboard.record(&status);
+            }
+            results.push(status);
+
+            if let (Some(every), Some(path), Some(format)) = (cli.flush_every, &cli.output_file, file_format) {
+                if every > 0 && results.len() % every == 0 {
+                    let mut partial = create_check_result(results.clone(), Utc::now().to_rfc3339());
+                    partial.config = config.clone();
+                    partial.manifest = Some(manifest.clone());
+                    let mut partial = filter_results(partial, &filter);
+                    if cli.stable {
+                        partial.stabilize();
+                    }
+                    let written = render_file_output(&partial, format, JsonLayout::from(&cli), cli.stable)
+                        .and_then(|contents| Ok(write_atomic(path, &contents)?));
+                    if let Err(e) = written {
+                        eprintln!("Warning: failed to flush partial results to {}: {}", path.display(), e);
+                    }
+                }
+            }
+        }
+        if finished {
+            break;
         }
+    }
+    drop(statuses);
+    drop(dashboard);
 
-        if let Some(error) = &status.error {
-            println!("Error: {}", error);
+    if let Some((tx, handle)) = webhook {
+        drop(tx);
+        if let Err(e) = handle.await {
+            eprintln!("Error: webhook sink failed: {}", e);
+            std::process::exit(1);
         }
     }
-}
 
-fn read_domains_from_stdin(clean: bool) -> io::Result<Vec<String>> {
-    let stdin = io::stdin();
-    let mut domains = Vec::new();
+    let timestamp = Utc::now().to_rfc3339();
 
-    for line in stdin.lock().lines() {
-        let line = line?;
-        if clean {
-            let trimmed = line.trim();
-            if !trimmed.is_empty() {
-                domains.push(trimmed.to_string());
-            }
-        } else {
-            domains.push(line);
+    let mut check_result = create_check_result(results, timestamp);
+    check_result.input_files = summarize_input_files(&input_files, &check_result.domains);
+    check_result.config = config;
+    check_result.stats = latency.and_then(|mut latency| latency.stats());
+    // Judged before any filtering, which could hide how many checks failed
+    if let Some((failed, total)) = systemic_failure(&check_result.domains) {
+        eprintln!(
+            "Warning: {} of {} checks failed; the resolver or its configuration (--nameserver, --resolver, \
+             --timeout, network access) is the likely cause rather than the domains",
+            failed, total
+        );
+        if let Some(stats) = &mut check_result.stats {
+            stats.systemic_failure = true;
+        }
+    }
+    let sparkline = if cli.latency_sparkline { latency_sparkline(&check_result.domains) } else { None };
+    if cli.sample.is_some() {
+        // Priority domains were checked regardless of the draw, so they'd bias the estimate
+        let sampled: Vec<DomainStatus> =
+            check_result.domains.iter().filter(|d| !priority_set.contains(&d.domain)).cloned().collect();
+        check_result.estimate = SampleEstimate::from_domains(&sampled, population);
+    }
+    manifest.finished_at = Some(check_result.timestamp.clone());
+    check_result.manifest = Some(manifest);
+    // Explicit --exit-code-on rules first, in the order given, then the --fail-on-* style flags.
+    // They judge every checked domain, so output filters can't hide a failure
+    let mut exit_rules = cli.exit_code_on.clone();
+    for (enabled, condition) in [
+        (!cli.assertions.is_empty(), ExitCondition::AssertionFailed),
+        (cli.assert_ips, ExitCondition::IpMismatch),
+        (cli.fail_on_caa, ExitCondition::CaaNoncompliant),
+        (cli.fail_on_blocklisted, ExitCondition::Blocklisted),
+    ] {
+        if enabled {
+            exit_rules.push((condition, 1));
         }
     }
+    let exit = exit_rules.into_iter().find_map(|(condition, code)| {
+        let (count, description) = condition.count(&check_result);
+        (count > 0).then_some((count, description, code))
+    });
 
-    Ok(domains)
-}
+    let mut filtered_result = filter_results(check_result, &filter);
+    if let (Some(path), Some(notified)) = (&cli.notify_once, &filter.notified) {
+        let mut remembered: Vec<&str> = notified.iter().map(String::as_str).collect();
+        remembered.extend(filtered_result.domains.iter().map(|d| d.domain.as_str()));
+        remembered.sort_unstable();
+        remembered.dedup();
+        let contents: String = remembered.iter().map(|domain| format!("{}\n", domain)).collect();
+        if let Err(e) = write_atomic(path, &contents) {
+            eprintln!("Warning: failed to update {}: {}", path.display(), e);
+        }
+    }
+    if cli.stable {
+        filtered_result.stabilize();
+    }
+    if cli.infra_report {
+        filtered_result.infra_report = Some(InfraReport::from_domains(&filtered_result.domains));
+    }
+    if cli.registrar_report {
+        filtered_result.registrars = Some(RegistrarGroup::from_domains(&filtered_result.domains));
+    }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let cli = Cli::parse();
-    let checker = DomainChecker::new().await;
+    if cli.events {
+        Event::Finished { summary: &filtered_result.summary }.emit();
+    }
+    Event::Finished { summary: &filtered_result.summary }.broadcast(&sse).await;
+    if let Some((tx, handle)) = sse {
+        drop(tx);
+        handle.await?;
+    }
 
-    // Get domains from either command line args or stdin
-    let domains = if cli.domains.is_empty() {
-        // No domains provided as arguments, try reading from stdin
-        read_domains_from_stdin(cli.clean)?
+    // Handle output based on flags. With only --output-file given, stdout stays quiet
+    let stdout_format = if cli.json {
+        Some(OutputFormat::Json)
+    } else if cli.logfmt {
+        Some(OutputFormat::Logfmt)
+    } else if cli.format.is_some() {
+        cli.format
+    } else if cli.events || cli.output_file.is_some() {
+        None
+    } else {
+        Some(OutputFormat::Text)
+    };
+    let json = if stdout_format == Some(OutputFormat::Json) || file_format == Some(OutputFormat::Json) {
+        let (mut value, duplicates) = result_to_json(&filtered_result, JsonLayout::from(&cli))?;
+        if cli.stable {
+            value.sort_all_objects();
+        }
+        for domain in duplicates {
+            eprintln!("Warning: duplicate domain '{}' in keyed JSON output, keeping the last result", domain);
+        }
+        Some(value)
     } else {
-        cli.domains
+        None
     };
 
-    // Verify we have domains to check
-    if domains.is_empty() {
-        eprintln!("Error: No domains provided. Either specify domains as arguments or pipe them through stdin.");
-        std::process::exit(1);
+    match (stdout_format, &json) {
+        (Some(OutputFormat::Json), Some(value)) if cli.color_json.enabled() => println!("{}", colorize_json(value)),
+        (Some(OutputFormat::Json), Some(value)) => println!("{}", serde_json::to_string_pretty(value)?),
+        (Some(format), _) => print!("{}", render_output(&filtered_result, format, json.as_ref())?),
+        (None, _) => {}
     }
 
-    let results = checker
-        .check_domains(domains, cli.concurrent)
-        .await;
-
-    let timestamp = Utc::now().to_rfc3339();
+    if let (Some(path), Some(format)) = (&cli.output_file, file_format) {
+        write_atomic(path, &render_output(&filtered_result, format, json.as_ref())?)?;
+    }
 
-    let check_result = create_check_result(results, timestamp);
-    let filtered_result = filter_results(check_result, cli.unregistered_only);
+    if let Some(dir) = &cli.split_by_status {
+        fs::create_dir_all(dir)?;
+        for (name, bucket) in split_by_status(&filtered_result) {
+            let path = dir.join(format!("{}.json", name));
+            if bucket.domains.is_empty() {
+                // Don't leave a previous run's bucket behind to be mistaken for this one's
+                match fs::remove_file(&path) {
+                    Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+                    _ => continue,
+                }
+            }
+            let (mut value, _) = result_to_json(&bucket, JsonLayout::from(&cli))?;
+            if cli.stable {
+                value.sort_all_objects();
+            }
+            write_atomic(&path, &serde_json::to_string_pretty(&value)?)?;
+        }
+    }
 
-    // Handle output based on flags
-    if cli.json || cli.output_file.is_some() {
-        let json = serde_json::to_string_pretty(&filtered_result)?;
+    if let Some(path) = &cli.msgpack {
+        // Named fields keep the encoding readable as the structs gain optional fields
+        fs::write(path, rmp_serde::to_vec_named(&filtered_result)?)?;
+    }
 
-        if cli.json {
-            println!("{}", json);
+    if let Some(path) = &cli.openmetrics_file {
+        let domains = &filtered_result.domains;
+        if domains.len() > cli.openmetrics_max_domains {
+            eprintln!(
+                "Warning: writing OpenMetrics series for the first {} of {} domains (see --openmetrics-max-domains)",
+                cli.openmetrics_max_domains,
+                domains.len()
+            );
         }
+        let limit = domains.len().min(cli.openmetrics_max_domains);
+        write_atomic(path, &openmetrics(&domains[..limit]))?;
+    }
 
-        if let Some(path) = cli.output_file {
-            fs::write(path, json)?;
-        }
-    } else {
-        print_text_output(&filtered_result);
+    if let Some(sparkline) = sparkline {
+        eprintln!("{}", sparkline);
+    }
+
+    if let Some((count, description, code)) = exit {
+        eprintln!("Error: {} domain(s) {}", count, description);
+        std::process::exit(code);
     }
 
     Ok(())